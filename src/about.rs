@@ -0,0 +1,273 @@
+//! Pages Nasir draws itself, at `about:` urls
+
+use crossterm::terminal;
+use url::Url;
+
+use crate::{
+    cacher::ByteCacher, download::State, types::prelude::*, utils::format_bytes, utils::wrap,
+};
+
+/// every about page, for the page that lists them
+pub const PAGES: [&str; 7] = [
+    "blank",
+    "bookmarks",
+    "cache",
+    "config",
+    "downloads",
+    "history",
+    "keys",
+];
+
+/// how many characters wide the bar showing how far a download's got is
+const PROGRESS_WIDTH: u64 = 20;
+
+/// draw an about page; `keys` is every key the browser listens for, with what it does
+pub fn render(name: &str, cacher: &ByteCacher, keys: &[(&str, &str)]) -> Vec<TerminalLine> {
+    match name {
+        // a page with no lines can't have a cursor on it
+        "blank" => vec![TerminalLine::from("")],
+        "history" => history(cacher),
+        "bookmarks" => bookmarks(cacher),
+        "cache" => cache(cacher),
+        "config" => config(cacher),
+        "downloads" => downloads(cacher),
+        "keys" => keys_page(keys),
+        _ => index(name),
+    }
+}
+
+/// pure fn to start a page with its title
+fn heading(title: &str) -> Vec<TerminalLine> {
+    vec![
+        TerminalLine::from(format!("\x1b[30;47m{title}\x1b[0m")),
+        TerminalLine::from(""),
+    ]
+}
+
+/// pure fn to make a line that's a link, labelled with a page's title or its url if it doesn't have one
+fn link(title: &str, url: &str) -> TerminalLine {
+    let label = if title.is_empty() { url } else { title };
+    TerminalLine::from(label).into_link(url.into())
+}
+
+/// add a paragraph wrapped to the screen
+fn paragraph(page: &mut Vec<TerminalLine>, txt: &str) {
+    let width = (terminal::size().unwrap_or((80, 1)).0 - 1) as usize;
+    page.extend(wrap(txt, width).into_iter().map(TerminalLine::from));
+}
+
+/// every page visited, most recent first
+fn history(cacher: &ByteCacher) -> Vec<TerminalLine> {
+    let mut page = heading("History");
+    let visits = cacher.history().visits();
+    if visits.is_empty() {
+        paragraph(&mut page, "Nothing's been visited yet.");
+    }
+    page.extend(visits.iter().rev().map(|visit| {
+        let date = httpdate::fmt_http_date(visit.time);
+        // `Sun, 18 Oct 2026 12:00:00 GMT` is more than anyone needs to see
+        let date = date.get(5..22).unwrap_or(&date);
+        link(&visit.title, &visit.url).map(|rstr| format!("\x1b[2m{date}\x1b[0m  {rstr}").into())
+    }));
+    page
+}
+
+/// every bookmark, in the order they were added
+fn bookmarks(cacher: &ByteCacher) -> Vec<TerminalLine> {
+    let mut page = heading("Bookmarks");
+    let bookmarks = cacher.bookmarks().bookmarks();
+    if bookmarks.is_empty() {
+        paragraph(
+            &mut page,
+            "There aren't any bookmarks yet. Press `b` on a page to bookmark it.",
+        );
+    }
+    page.extend(
+        bookmarks
+            .iter()
+            .map(|bookmark| link(&bookmark.title, &bookmark.url)),
+    );
+    page
+}
+
+/// everything in the cache, with what state it's in and how big it is
+fn cache(cacher: &ByteCacher) -> Vec<TerminalLine> {
+    let mut page = heading("Cache");
+    let summaries = cacher.summaries();
+    let in_memory: u64 = summaries
+        .iter()
+        .filter(|summary| summary.state != "saved")
+        .map(|summary| summary.bytes)
+        .sum();
+    paragraph(
+        &mut page,
+        &format!(
+            "{} entries, using {} of memory.",
+            summaries.len(),
+            size(in_memory)
+        ),
+    );
+    if let Some((used, budget)) = cacher.disk_usage() {
+        paragraph(
+            &mut page,
+            &format!(
+                "Saved responses use {} of {} on disk.",
+                size(used),
+                size(budget)
+            ),
+        );
+    }
+    if cacher.is_offline() {
+        paragraph(&mut page, "Offline: only what's here will load.");
+    }
+    page.push(TerminalLine::from(""));
+    let width = summaries
+        .iter()
+        .map(|summary| summary.state.len())
+        .max()
+        .unwrap_or(0);
+    page.extend(summaries.iter().map(|summary| {
+        let bytes = if summary.bytes == 0 {
+            String::from("-")
+        } else {
+            size(summary.bytes)
+        };
+        let state = &summary.state;
+        link("", &summary.url).map(|rstr| format!("{state:width$}  {bytes:>9}  {rstr}").into())
+    }));
+    page
+}
+
+/// pure fn to show a number of bytes, however many there are
+fn size(bytes: u64) -> String {
+    format_bytes(usize::try_from(bytes).unwrap_or(usize::MAX))
+}
+
+/// every download this session, newest first, with how far along it is and a link to cancel, resume or open it
+fn downloads(cacher: &ByteCacher) -> Vec<TerminalLine> {
+    let mut page = heading("Downloads");
+    let downloads = cacher.downloads();
+    if downloads.is_empty() {
+        paragraph(
+            &mut page,
+            "Nothing's been downloaded yet. Following a link to a file Nasir can't show asks where to save it.",
+        );
+    }
+    for (id, download) in downloads.iter().enumerate().rev() {
+        let name = download.path.file_name().map_or_else(
+            || download.url.clone(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+        let (state, action) = match &download.state {
+            State::Going => (
+                String::from("\x1b[94mdownloading\x1b[0m"),
+                Some(("cancel", format!("about:downloads?cancel={id}"))),
+            ),
+            State::Done => (
+                String::from("\x1b[92mdone\x1b[0m"),
+                Url::from_file_path(&download.path)
+                    .ok()
+                    .map(|url| ("open", String::from(url))),
+            ),
+            State::Failed(err) => (
+                format!("\x1b[91mfailed: {err}\x1b[0m"),
+                Some(("resume", format!("about:downloads?resume={id}"))),
+            ),
+            State::Cancelled => (
+                String::from("cancelled"),
+                Some(("resume", format!("about:downloads?resume={id}"))),
+            ),
+        };
+        let progress = match download.total {
+            Some(total) if total > 0 => {
+                let received = download.received.min(total);
+                let filled = usize::try_from(received * PROGRESS_WIDTH / total).unwrap_or(0);
+                let empty = usize::try_from(PROGRESS_WIDTH).unwrap_or(0) - filled;
+                format!(
+                    "[{}{}] {} of {}",
+                    "#".repeat(filled),
+                    " ".repeat(empty),
+                    size(received),
+                    size(total)
+                )
+            }
+            _ => size(download.received),
+        };
+        page.push(TerminalLine::from(format!("\x1b[1m{name}\x1b[0m  {state}")));
+        page.push(TerminalLine::from(format!("  {progress}")));
+        page.push(TerminalLine::from(format!(
+            "  \x1b[2m{}\x1b[0m",
+            download.path.display()
+        )));
+        if let Some((label, url)) = action {
+            page.push(link(label, &url).map(|rstr| format!("  {rstr}").into()));
+        }
+        page.push(TerminalLine::from(""));
+    }
+    page
+}
+
+/// the settings in effect, after flags and environment variables
+fn config(cacher: &ByteCacher) -> Vec<TerminalLine> {
+    let mut page = heading("Config");
+    paragraph(
+        &mut page,
+        "The settings in effect, from the config file, then command line flags and environment variables:",
+    );
+    page.push(TerminalLine::from(""));
+    match toml::to_string(&cacher.config().redacted()) {
+        Ok(toml) => page.extend(toml.lines().map(TerminalLine::from)),
+        Err(err) => page.push(TerminalLine::from(format!("Config Error: {err}"))),
+    }
+    page
+}
+
+/// every key the browser listens for, with what it does
+fn keys_page(keys: &[(&str, &str)]) -> Vec<TerminalLine> {
+    let mut page = heading("Keys");
+    let width = keys.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+    page.extend(
+        keys.iter().map(|(key, action)| {
+            TerminalLine::from(format!("\x1b[1m{key:width$}\x1b[0m  {action}"))
+        }),
+    );
+    page
+}
+
+/// list every about page, explaining first if the one asked for doesn't exist
+fn index(name: &str) -> Vec<TerminalLine> {
+    let mut page = heading("About");
+    if !name.is_empty() && name != "about" {
+        paragraph(
+            &mut page,
+            &format!("There's no about:{name} page. These are the ones there are:"),
+        );
+        page.push(TerminalLine::from(""));
+    }
+    page.extend(PAGES.iter().map(|page| {
+        let url = format!("about:{page}");
+        link(&url, &url)
+    }));
+    page
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{index, keys_page, PAGES};
+    use crate::types::prelude::*;
+
+    #[test]
+    fn pages() {
+        let keys = keys_page(&[("b", "bookmark"), ("page up", "go up")]);
+        assert_eq!(keys.len(), 4);
+        assert_eq!(keys[2].display(false), " \x1b[1mb      \x1b[0m  bookmark");
+        let about = index("about");
+        assert_eq!(about.len(), PAGES.len() + 2);
+        assert_eq!(
+            about[2].interaction(),
+            &InteractionType::Link(RStr::from("about:blank"))
+        );
+        // a page that doesn't exist says so above the list
+        assert!(index("nope").len() > about.len());
+    }
+}
@@ -0,0 +1,97 @@
+//! Pages the user wants to come back to, saved between sessions
+
+use std::{fs, path::PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bookmark {
+    pub url: String,
+    /// the page's title when it was bookmarked, or empty if it didn't have one
+    pub title: String,
+}
+
+/// every bookmark, in the order they were added, optionally saved to a file
+#[derive(Default)]
+pub struct Bookmarks {
+    path: Option<PathBuf>,
+    bookmarks: Vec<Bookmark>,
+}
+
+impl Bookmarks {
+    /// read bookmarks from a file, or keep them in memory if there isn't one
+    pub fn open(path: Option<PathBuf>) -> Self {
+        let bookmarks = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|file| {
+                file.lines()
+                    .filter(|line| !line.is_empty())
+                    .map(|line| {
+                        let (url, title) = line.split_once('\t').unwrap_or((line, ""));
+                        Bookmark {
+                            url: String::from(url),
+                            title: String::from(title),
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { path, bookmarks }
+    }
+
+    /// pure fn to get every bookmark, oldest first
+    pub fn bookmarks(&self) -> &[Bookmark] {
+        &self.bookmarks
+    }
+
+    /// bookmark a page, or forget its bookmark if it has one; returns whether it's bookmarked now
+    pub fn toggle(&mut self, url: &str, title: &str) -> bool {
+        let before = self.bookmarks.len();
+        self.bookmarks.retain(|bookmark| bookmark.url != url);
+        let added = self.bookmarks.len() == before;
+        if added {
+            self.bookmarks.push(Bookmark {
+                url: String::from(url),
+                title: title.split_whitespace().collect::<Vec<_>>().join(" "),
+            });
+        }
+        self.save();
+        added
+    }
+
+    /// write every bookmark to the file
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let file: String = self
+            .bookmarks
+            .iter()
+            .flat_map(|bookmark| [bookmark.url.as_str(), "\t", &bookmark.title, "\n"])
+            .collect();
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = fs::write(path, file);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::Bookmarks;
+
+    #[test]
+    fn toggling() {
+        let path = std::env::temp_dir().join(format!("nasir-bookmarks-{}", std::process::id()));
+        let mut bookmarks = Bookmarks::open(Some(path.clone()));
+        assert!(bookmarks.toggle("https://example.com/", "Example"));
+        assert!(bookmarks.toggle("gemini://example.org/", ""));
+        let mut bookmarks = Bookmarks::open(Some(path.clone()));
+        assert_eq!(bookmarks.bookmarks().len(), 2);
+        assert_eq!(bookmarks.bookmarks()[0].title, "Example");
+        assert!(!bookmarks.toggle("https://example.com/", "Example"));
+        assert_eq!(bookmarks.bookmarks()[0].url, "gemini://example.org/");
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,525 @@
+//! Cookie storage shared by every request, following RFC 6265
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use cookie::{Cookie, SameSite};
+use publicsuffix::{List, Psl};
+use reqwest::{cookie::CookieStore, header::HeaderValue};
+use url::Url;
+
+/// a cookie the jar is holding onto
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredCookie {
+    pub name: String,
+    pub value: String,
+    /// lowercase, without a leading `.`
+    pub domain: String,
+    /// if set, the cookie only goes back to exactly `domain` and not its subdomains
+    pub host_only: bool,
+    pub path: String,
+    pub secure: bool,
+    pub http_only: bool,
+    pub same_site: SameSite,
+    /// unix time when the cookie expires; `None` for cookies that only last the session
+    pub expires: Option<i64>,
+}
+
+#[derive(Default)]
+struct JarState {
+    /// cookies in the order they were created
+    cookies: Vec<StoredCookie>,
+    /// host of the page being browsed, so `SameSite` cookies stay off cross-site requests
+    top_level_host: Option<String>,
+    /// the request the page is being loaded with, which gets the cookies a top-level request does
+    navigation: Option<Navigation>,
+}
+
+/// a request for the page itself, followed through its redirects
+struct Navigation {
+    /// the url it's at now, without a fragment
+    url: Url,
+    /// whether it came from or went through another site, which keeps `Strict` cookies off it
+    cross_site: bool,
+    /// whether it's a form from another site that hasn't been redirected yet, which keeps `Lax` cookies off it too
+    cross_site_post: bool,
+}
+
+/// every cookie the browser knows about, optionally saved to a file between sessions
+#[derive(Default)]
+#[allow(clippy::module_name_repetitions)]
+pub struct CookieJar {
+    state: Mutex<JarState>,
+    path: Option<PathBuf>,
+}
+
+impl CookieJar {
+    /// load the jar saved in the user's data directory
+    pub fn load() -> Self {
+        Self::open(dirs::data_dir().map(|dir| dir.join("nasir").join("cookies.txt")))
+    }
+
+    /// read cookies from a file, or keep them in memory if there isn't one
+    pub fn open(path: Option<PathBuf>) -> Self {
+        let cookies = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|file| file.lines().filter_map(parse_line).collect())
+            .unwrap_or_default();
+        Self {
+            state: Mutex::new(JarState {
+                cookies,
+                top_level_host: None,
+                navigation: None,
+            }),
+            path,
+        }
+    }
+
+    /// tell the jar which page the user is looking at, which is what gets loaded next
+    pub fn set_top_level(&self, url: &str) {
+        let mut state = self.state.lock().unwrap();
+        let url = Url::parse(url).ok();
+        state.top_level_host = url.as_ref().and_then(host_of);
+        state.navigation = url.map(|url| Navigation {
+            url: without_fragment(&url),
+            cross_site: false,
+            cross_site_post: false,
+        });
+    }
+
+    /// tell the jar a form on the page is being sent to a url, which is where the page goes next
+    pub fn begin_post(&self, url: &str) {
+        let Ok(url) = Url::parse(url) else {
+            return;
+        };
+        let mut state = self.state.lock().unwrap();
+        let cross_site = !same_site(state.top_level_host.as_deref(), host_of(&url).as_deref());
+        state.navigation = Some(Navigation {
+            url: without_fragment(&url),
+            cross_site,
+            cross_site_post: cross_site,
+        });
+    }
+
+    /// tell the jar a request redirected, so the page it's loading moves along with it
+    pub fn redirected(&self, from: &Url, to: &Url) {
+        let crossed = !same_site(host_of(from).as_deref(), host_of(to).as_deref());
+        let (from, to) = (without_fragment(from), without_fragment(to));
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        if let Some(navigation) = state
+            .navigation
+            .as_mut()
+            .filter(|navigation| navigation.url == from)
+        {
+            navigation.cross_site |= crossed;
+            navigation.cross_site_post = false;
+            state.top_level_host = host_of(&to);
+            navigation.url = to;
+        }
+        drop(guard);
+    }
+
+    /// pure fn to list the unexpired cookies for a site and its subdomains, or every cookie
+    pub fn list(&self, site: Option<&str>) -> Vec<StoredCookie> {
+        let now = now();
+        self.state
+            .lock()
+            .unwrap()
+            .cookies
+            .iter()
+            .filter(|cookie| cookie.expires.is_none_or(|expires| expires > now))
+            .filter(|cookie| site.is_none_or(|site| domain_match(&cookie.domain, site)))
+            .cloned()
+            .collect()
+    }
+
+    /// forget the cookies for a site and its subdomains, or every cookie
+    pub fn clear(&self, site: Option<&str>) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .cookies
+            .retain(|cookie| site.is_some_and(|site| !domain_match(&cookie.domain, site)));
+        self.save(&state.cookies);
+    }
+
+    /// write the cookies that outlast the session to the jar's file
+    fn save(&self, cookies: &[StoredCookie]) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let mut file = String::from("# Nasir cookie jar\n");
+        for cookie in cookies {
+            if let Some(expires) = cookie.expires {
+                file.push_str(
+                    &[
+                        cookie.domain.as_str(),
+                        bool_field(!cookie.host_only),
+                        &cookie.path,
+                        bool_field(cookie.secure),
+                        &expires.to_string(),
+                        &cookie.name,
+                        &cookie.value,
+                        bool_field(cookie.http_only),
+                        &cookie.same_site.to_string(),
+                    ]
+                    .join("\t"),
+                );
+                file.push('\n');
+            }
+        }
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = write_private(path, &file);
+    }
+}
+
+/// write a file only the user can read, since the cookies in it log them into sites
+fn write_private(path: &Path, contents: &str) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    // a file from before keeps the permissions it was made with, unless they're changed
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    file.write_all(contents.as_bytes())
+}
+
+impl CookieStore for CookieJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        let Some(host) = url.host_str().map(str::to_lowercase) else {
+            return;
+        };
+        let now = now();
+        let mut state = self.state.lock().unwrap();
+        let mut changed = false;
+        for header in cookie_headers {
+            let Some(cookie) = header
+                .to_str()
+                .ok()
+                .and_then(|header| Cookie::parse(header).ok())
+            else {
+                continue;
+            };
+            let Some(cookie) = receive_cookie(&cookie, url, &host, now) else {
+                continue;
+            };
+            // a new cookie replaces an old one with the same name, domain and path
+            state.cookies.retain(|old| {
+                (&old.name, &old.domain, &old.path) != (&cookie.name, &cookie.domain, &cookie.path)
+            });
+            if cookie.expires.is_none_or(|expires| expires > now) {
+                state.cookies.push(cookie);
+            }
+            changed = true;
+        }
+        if changed {
+            self.save(&state.cookies);
+        }
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        let host = url.host_str()?.to_lowercase();
+        let now = now();
+        let mut state = self.state.lock().unwrap();
+        state
+            .cookies
+            .retain(|cookie| cookie.expires.is_none_or(|expires| expires > now));
+        // the page itself gets what a top-level request does, and everything on it what a request from it does
+        let navigation = state
+            .navigation
+            .as_ref()
+            .filter(|navigation| navigation.url == without_fragment(url));
+        let cross_site = !same_site(state.top_level_host.as_deref(), Some(&host));
+        let allowed = |same_site: SameSite| match (navigation, same_site) {
+            (_, SameSite::None) => true,
+            (Some(navigation), SameSite::Lax) => !navigation.cross_site_post,
+            (Some(navigation), SameSite::Strict) => !navigation.cross_site,
+            (None, _) => !cross_site,
+        };
+        let mut matching: Vec<&StoredCookie> = state
+            .cookies
+            .iter()
+            .filter(|cookie| {
+                if cookie.host_only {
+                    cookie.domain == host
+                } else {
+                    domain_match(&host, &cookie.domain)
+                }
+            })
+            .filter(|cookie| path_match(url.path(), &cookie.path))
+            .filter(|cookie| !cookie.secure || url.scheme() == "https")
+            .filter(|cookie| allowed(cookie.same_site))
+            .collect();
+        // longer paths go first; otherwise the oldest cookie goes first (the sort is stable)
+        matching.sort_by_key(|cookie| std::cmp::Reverse(cookie.path.len()));
+        let header = matching
+            .into_iter()
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        drop(state);
+        if header.is_empty() {
+            None
+        } else {
+            HeaderValue::from_str(&header).ok()
+        }
+    }
+}
+
+/// pure fn to decide whether to store a cookie from a `Set-Cookie` header, and how
+fn receive_cookie(cookie: &Cookie, url: &Url, host: &str, now: i64) -> Option<StoredCookie> {
+    let secure = cookie.secure().unwrap_or(false);
+    // only secure pages can set secure cookies
+    if secure && url.scheme() != "https" {
+        return None;
+    }
+    let (domain, host_only) = match cookie
+        .domain()
+        .map(|domain| domain.trim_start_matches('.').to_lowercase())
+    {
+        Some(domain) if !domain.is_empty() => {
+            // a page can't set cookies for another site
+            if !domain_match(host, &domain) {
+                return None;
+            }
+            // or for every site under a public suffix like `co.uk`, unless that's the host itself
+            if is_public_suffix(&domain) {
+                if domain != host {
+                    return None;
+                }
+                (domain, true)
+            } else {
+                (domain, false)
+            }
+        }
+        _ => (String::from(host), true),
+    };
+    let path = match cookie.path() {
+        Some(path) if path.starts_with('/') => String::from(path),
+        _ => default_path(url.path()),
+    };
+    let same_site = match cookie.same_site() {
+        // `SameSite=None` is only allowed on secure cookies
+        Some(SameSite::None) if !secure => return None,
+        Some(same_site) => same_site,
+        None => SameSite::Lax,
+    };
+    // `Max-Age` wins over `Expires`
+    let expires = cookie
+        .max_age()
+        .map(|max_age| now.saturating_add(max_age.whole_seconds()))
+        .or_else(|| {
+            cookie
+                .expires()
+                .and_then(cookie::Expiration::datetime)
+                .map(cookie::time::OffsetDateTime::unix_timestamp)
+        });
+    Some(StoredCookie {
+        name: String::from(cookie.name()),
+        value: String::from(cookie.value()),
+        domain,
+        host_only,
+        path,
+        secure,
+        http_only: cookie.http_only().unwrap_or(false),
+        same_site,
+        expires,
+    })
+}
+
+/// pure fn to read a cookie from a line of the jar's file
+fn parse_line(line: &str) -> Option<StoredCookie> {
+    if line.starts_with('#') {
+        return None;
+    }
+    let mut fields = line.split('\t');
+    let mut next = || fields.next();
+    Some(StoredCookie {
+        domain: String::from(next()?),
+        host_only: next()? != "TRUE",
+        path: String::from(next()?),
+        secure: next()? == "TRUE",
+        expires: Some(next()?.parse().ok()?),
+        name: String::from(next()?),
+        value: String::from(next()?),
+        http_only: next()? == "TRUE",
+        same_site: match next()? {
+            "Strict" => SameSite::Strict,
+            "None" => SameSite::None,
+            _ => SameSite::Lax,
+        },
+    })
+}
+
+const fn bool_field(value: bool) -> &'static str {
+    if value {
+        "TRUE"
+    } else {
+        "FALSE"
+    }
+}
+
+/// current unix time in seconds
+#[allow(clippy::cast_possible_wrap)]
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs() as i64)
+}
+
+/// pure fn to check if a host is the domain or one of its subdomains
+fn domain_match(host: &str, domain: &str) -> bool {
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+/// pure fn to check if a request path is under a cookie's path
+fn path_match(request_path: &str, cookie_path: &str) -> bool {
+    request_path == cookie_path
+        || request_path
+            .strip_prefix(cookie_path)
+            .is_some_and(|rest| cookie_path.ends_with('/') || rest.starts_with('/'))
+}
+
+/// pure fn to get the path a cookie applies to when it doesn't say
+fn default_path(request_path: &str) -> String {
+    match request_path.rfind('/') {
+        Some(0) | None => String::from("/"),
+        Some(idx) => String::from(&request_path[..idx]),
+    }
+}
+
+/// the public suffix list, which says which domains are shared by sites that have nothing to do with each other,
+/// like `co.uk` and `github.io`
+fn public_suffixes() -> &'static List {
+    static LIST: OnceLock<List> = OnceLock::new();
+    LIST.get_or_init(|| include_str!("public_suffix_list.dat").parse().unwrap())
+}
+
+/// pure fn to check if a domain is a public suffix; a top-level domain that isn't on the list counts as one
+fn is_public_suffix(domain: &str) -> bool {
+    public_suffixes()
+        .suffix(domain.as_bytes())
+        .is_some_and(|suffix| suffix.as_bytes() == domain.as_bytes())
+}
+
+/// pure fn to get the site a host belongs to: its public suffix and the label before it
+fn site_of(host: &str) -> &str {
+    public_suffixes()
+        .domain(host.as_bytes())
+        .and_then(|domain| std::str::from_utf8(domain.as_bytes()).ok())
+        .unwrap_or(host)
+}
+
+/// pure fn to check if two hosts belong to the same site; a request with nothing to compare to counts as same-site
+fn same_site(top_level: Option<&str>, host: Option<&str>) -> bool {
+    match (top_level, host) {
+        (Some(top_level), Some(host)) => site_of(top_level) == site_of(host),
+        _ => true,
+    }
+}
+
+/// pure fn to get a url's host in lowercase
+fn host_of(url: &Url) -> Option<String> {
+    url.host_str().map(str::to_lowercase)
+}
+
+/// pure fn to take the fragment off a url, which never reaches the server
+fn without_fragment(url: &Url) -> Url {
+    let mut url = url.clone();
+    url.set_fragment(None);
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{cookie::CookieStore, header::HeaderValue};
+    use url::Url;
+
+    use super::{default_path, domain_match, is_public_suffix, path_match, site_of, CookieJar};
+
+    #[test]
+    fn matching() {
+        assert!(domain_match("en.wikipedia.org", "wikipedia.org"));
+        assert!(!domain_match("notwikipedia.org", "wikipedia.org"));
+        assert!(path_match("/docs/page", "/docs"));
+        assert!(!path_match("/docsx", "/docs"));
+        assert_eq!(default_path("/docs/page"), "/docs");
+        assert_eq!(default_path("/page"), "/");
+        assert_eq!(site_of("upload.wikimedia.org"), "wikimedia.org");
+        assert_eq!(site_of("www.example.co.uk"), "example.co.uk");
+        assert_eq!(site_of("me.github.io"), "me.github.io");
+        assert!(is_public_suffix("co.uk"));
+        assert!(!is_public_suffix("example.co.uk"));
+    }
+
+    #[test]
+    fn jar() {
+        let jar = CookieJar::default();
+        let url = Url::parse("https://en.example.com/a/b").unwrap();
+        let headers = [
+            HeaderValue::from_static("id=1; Domain=example.com; Path=/"),
+            HeaderValue::from_static("local=2"),
+            HeaderValue::from_static("evil=3; Domain=com"),
+        ];
+        jar.set_cookies(&mut headers.iter(), &url);
+        let other = Url::parse("https://www.example.com/").unwrap();
+        assert_eq!(jar.cookies(&other).unwrap(), "id=1");
+        assert_eq!(jar.cookies(&url).unwrap(), "local=2; id=1");
+        jar.set_top_level("https://elsewhere.org");
+        assert_eq!(jar.cookies(&url), None);
+        // a public suffix is shared by every site under it
+        let shared = Url::parse("https://me.github.io/").unwrap();
+        let headers = [HeaderValue::from_static("evil=4; Domain=github.io")];
+        jar.set_top_level("https://me.github.io/");
+        jar.set_cookies(&mut headers.iter(), &shared);
+        assert_eq!(
+            jar.cookies(&Url::parse("https://you.github.io/").unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn same_site_redirects() {
+        let jar = CookieJar::default();
+        let sso = Url::parse("https://sso.example.org/login").unwrap();
+        let headers = [
+            HeaderValue::from_static("strict=1; SameSite=Strict"),
+            HeaderValue::from_static("lax=2"),
+            HeaderValue::from_static("none=3; SameSite=None; Secure"),
+        ];
+        jar.set_cookies(&mut headers.iter(), &sso);
+        let app = Url::parse("https://app.example.com/#top").unwrap();
+        jar.set_top_level(app.as_str());
+        // an image from another site only gets the cookies that are meant for anywhere
+        assert_eq!(jar.cookies(&sso).unwrap(), "none=3");
+        // the page redirecting there is still the page, but it came from another site
+        jar.redirected(&app, &sso);
+        assert_eq!(jar.cookies(&sso).unwrap(), "lax=2; none=3");
+        // and everything on the page it ended up at belongs to that site
+        let avatar = Url::parse("https://sso.example.org/me.png").unwrap();
+        assert_eq!(jar.cookies(&avatar).unwrap(), "strict=1; lax=2; none=3");
+        // a form sent from another site only gets the cookies meant for anywhere until it redirects
+        jar.set_top_level(app.as_str());
+        jar.begin_post(sso.as_str());
+        assert_eq!(jar.cookies(&sso).unwrap(), "none=3");
+        let home = Url::parse("https://sso.example.org/").unwrap();
+        jar.redirected(&sso, &home);
+        assert_eq!(jar.cookies(&home).unwrap(), "lax=2; none=3");
+        // going there directly sends everything
+        jar.set_top_level(sso.as_str());
+        assert_eq!(jar.cookies(&sso).unwrap(), "strict=1; lax=2; none=3");
+    }
+}
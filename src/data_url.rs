@@ -0,0 +1,126 @@
+//! `data:` urls, which carry their content in the url itself instead of pointing somewhere
+
+use base64::{
+    alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
+use encoding_rs::Encoding;
+use percent_encoding::percent_decode_str;
+
+/// base64 in urls often leaves off the padding
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// what a `data:` url holds
+#[derive(Debug, PartialEq, Eq)]
+pub struct DataUrl {
+    /// lowercase, like `image/png`
+    pub mime_type: String,
+    /// the content; text is always converted to UTF-8
+    pub bytes: Vec<u8>,
+}
+
+/// pure fn to read the content out of a `data:` url
+pub fn parse(url: &str) -> Result<DataUrl, String> {
+    let rest = url
+        .get(..5)
+        .filter(|scheme| scheme.eq_ignore_ascii_case("data:"))
+        .map(|_| &url[5..])
+        .ok_or_else(|| format!("Not a data url: {url}"))?;
+    let (header, data) = rest
+        .split_once(',')
+        .ok_or_else(|| String::from("Data url has no comma before its content"))?;
+    // the fragment belongs to the url, not the content
+    let data = data.split_once('#').map_or(data, |(data, _)| data);
+    let mut params = header.split(';').map(str::trim);
+    let mime_type = params
+        .next()
+        .filter(|mime_type| mime_type.contains('/'))
+        .map_or_else(|| String::from("text/plain"), str::to_lowercase);
+    let mut charset = None;
+    let mut base64 = false;
+    for param in params {
+        if param.eq_ignore_ascii_case("base64") {
+            base64 = true;
+        } else if let Some((name, value)) = param.split_once('=') {
+            if name.trim().eq_ignore_ascii_case("charset") {
+                charset = Some(value.trim().trim_matches('"').to_lowercase());
+            }
+        }
+    }
+    let mut bytes: Vec<u8> = percent_decode_str(data).collect();
+    if base64 {
+        bytes.retain(|byte| !byte.is_ascii_whitespace());
+        bytes = BASE64
+            .decode(&bytes)
+            .map_err(|err| format!("Data url isn't valid base64: {err}"))?;
+    }
+    if mime_type.starts_with("text/") {
+        bytes = to_utf8(bytes, charset.as_deref().unwrap_or("us-ascii"));
+    }
+    Ok(DataUrl { mime_type, bytes })
+}
+
+/// pure fn to convert text to UTF-8, going by the charset labels browsers know. Text in a charset without one is
+/// left as it is
+fn to_utf8(bytes: Vec<u8>, charset: &str) -> Vec<u8> {
+    match Encoding::for_label(charset.as_bytes()) {
+        Some(encoding) => encoding
+            .decode_without_bom_handling(&bytes)
+            .0
+            .into_owned()
+            .into_bytes(),
+        None => bytes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, DataUrl};
+
+    fn data(mime_type: &str, bytes: &[u8]) -> DataUrl {
+        DataUrl {
+            mime_type: String::from(mime_type),
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    #[test]
+    fn decoding() {
+        assert_eq!(
+            parse("data:,Hello%2C%20World!").unwrap(),
+            data("text/plain", b"Hello, World!")
+        );
+        assert_eq!(
+            parse("data:text/html;base64,PGI+aGk8L2I+").unwrap(),
+            data("text/html", b"<b>hi</b>")
+        );
+        // no padding, line breaks and percent-encoding all happen in the wild
+        assert_eq!(
+            parse("DATA:image/png;BASE64,iVBO%0ARw0K").unwrap(),
+            data("image/png", b"\x89PNG\r\n")
+        );
+        assert_eq!(
+            parse("data:text/plain;charset=ISO-8859-1,caf%E9#top").unwrap(),
+            data("text/plain", "café".as_bytes())
+        );
+        // Latin-1 is read as windows-1252, which has printable characters where Latin-1 has controls
+        assert_eq!(
+            parse("data:text/plain;charset=windows-1252,%93hi%94%20%80").unwrap(),
+            data("text/plain", "“hi” €".as_bytes())
+        );
+        assert_eq!(
+            parse("data:text/plain;charset=latin1,%85").unwrap(),
+            data("text/plain", "…".as_bytes())
+        );
+        assert_eq!(
+            parse("data:text/plain;charset=shift_jis,%93%FA%96%7B").unwrap(),
+            data("text/plain", "日本".as_bytes())
+        );
+        assert!(parse("data:text/plain;base64,!!!").is_err());
+        assert!(parse("data:no comma").is_err());
+    }
+}
@@ -0,0 +1,356 @@
+//! Responses saved between sessions, dropping the least recently used ones to stay under a size budget
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{encoding, http_cache::CachePolicy};
+
+/// file in the cache directory that lists what's saved
+const INDEX: &str = "index.toml";
+/// folder in the cache directory that holds response bodies
+const BODIES: &str = "bodies";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct IndexEntry {
+    /// name of the file in `BODIES` holding the response body
+    file: String,
+    size: u64,
+    /// hash of the body, to catch files that were changed or cut short
+    sha256: String,
+    /// content codings the body is still in, because that takes less room than the decoded body
+    #[serde(default)]
+    encoding: Vec<String>,
+    /// the index's `clock` when the entry was last read or written
+    last_used: u64,
+    policy: CachePolicy,
+}
+
+/// everything needed to find saved responses again
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Index {
+    /// counts every read and write, to tell which entries were used least recently
+    clock: u64,
+    /// urls that redirected, and where they ended up
+    pub redirects: BTreeMap<String, String>,
+    /// request headers that responses from a url depend on
+    pub vary: BTreeMap<String, Vec<String>>,
+    /// saved responses by the key the in-memory cache uses for them
+    entries: BTreeMap<String, IndexEntry>,
+}
+
+impl Index {
+    /// pure fn to check if a response is saved
+    pub fn contains(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// pure fn to get the key and size of every saved response
+    pub fn sizes(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.entries
+            .iter()
+            .map(|(key, entry)| (key.as_str(), entry.size))
+    }
+
+    /// advance the clock, returning the new time
+    const fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+}
+
+#[allow(clippy::module_name_repetitions)]
+pub struct DiskCache {
+    dir: PathBuf,
+    /// how many bytes of response bodies to keep
+    budget: u64,
+    index: Index,
+    /// whether the index changed since it was last saved
+    dirty: bool,
+}
+
+impl DiskCache {
+    /// pure fn to get where the cache lives by default
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("nasir"))
+    }
+
+    /// open the cache in a directory, dropping entries whose bodies are gone and bodies no entry uses.
+    /// A body newer than the index might belong to another Nasir that hasn't saved its index yet, so it stays
+    pub fn open(dir: PathBuf, budget: u64) -> Self {
+        let mut index: Index = fs::read_to_string(dir.join(INDEX))
+            .ok()
+            .and_then(|file| toml::from_str(&file).ok())
+            .unwrap_or_default();
+        let saved = fs::metadata(dir.join(INDEX)).and_then(|index| index.modified());
+        let bodies = dir.join(BODIES);
+        index
+            .entries
+            .retain(|_, entry| bodies.join(&entry.file).is_file());
+        let used: BTreeSet<&str> = index
+            .entries
+            .values()
+            .map(|entry| entry.file.as_str())
+            .collect();
+        for file in fs::read_dir(&bodies).into_iter().flatten().flatten() {
+            let unused = !file
+                .file_name()
+                .to_str()
+                .is_some_and(|name| used.contains(name));
+            let older = match (&saved, file.metadata().and_then(|body| body.modified())) {
+                (Ok(saved), Ok(written)) => written < *saved,
+                _ => false,
+            };
+            if unused && older {
+                let _ = fs::remove_file(file.path());
+            }
+        }
+        let mut cache = Self {
+            dir,
+            budget,
+            index,
+            dirty: false,
+        };
+        cache.evict();
+        cache.flush();
+        cache
+    }
+
+    /// delete everything saved in a cache directory
+    pub fn clear(dir: &Path) -> Result<(), String> {
+        match fs::remove_dir_all(dir) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("Couldn't clear {}: {err}", dir.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// pure fn to get how many bytes of response bodies the cache can keep
+    pub const fn budget(&self) -> u64 {
+        self.budget
+    }
+
+    /// pure fn to get the redirects and `Vary` headers saved from earlier sessions
+    pub const fn index(&self) -> &Index {
+        &self.index
+    }
+
+    /// read a saved response, decoded, forgetting it if the body isn't the one that was saved
+    pub fn read(&mut self, key: &str) -> Option<(Vec<u8>, CachePolicy)> {
+        let clock = self.index.tick();
+        let entry = self.index.entries.get_mut(key)?;
+        let bytes = fs::read(self.dir.join(BODIES).join(&entry.file))
+            .ok()
+            .filter(|bytes| bytes.len() as u64 == entry.size && checksum(bytes) == entry.sha256)
+            .and_then(|bytes| encoding::decode(&bytes, &entry.encoding).ok());
+        if let Some(bytes) = bytes {
+            entry.last_used = clock;
+            let policy = entry.policy.clone();
+            self.dirty = true;
+            Some((bytes, policy))
+        } else {
+            self.remove(key);
+            None
+        }
+    }
+
+    /// save a response body that went through the given content codings, making room for it if needed.
+    /// The index that lists it is saved by the next `flush`
+    pub fn write(&mut self, key: &str, bytes: &[u8], encoding: &[String], policy: &CachePolicy) {
+        let size = bytes.len() as u64;
+        if size > self.budget {
+            self.remove(key);
+            return;
+        }
+        let file = checksum(key.as_bytes());
+        let bodies = self.dir.join(BODIES);
+        if fs::create_dir_all(&bodies)
+            .and_then(|()| fs::write(bodies.join(&file), bytes))
+            .is_err()
+        {
+            return;
+        }
+        let last_used = self.index.tick();
+        self.index.entries.insert(
+            String::from(key),
+            IndexEntry {
+                file,
+                size,
+                sha256: checksum(bytes),
+                encoding: encoding.to_vec(),
+                last_used,
+                policy: policy.clone(),
+            },
+        );
+        self.dirty = true;
+        self.evict();
+    }
+
+    /// update what a saved response's headers say after it was revalidated
+    pub fn update_policy(&mut self, key: &str, policy: &CachePolicy) {
+        let clock = self.index.tick();
+        if let Some(entry) = self.index.entries.get_mut(key) {
+            entry.policy = policy.clone();
+            entry.last_used = clock;
+            self.dirty = true;
+        }
+    }
+
+    /// forget a saved response
+    pub fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.index.entries.remove(key) {
+            let _ = fs::remove_file(self.dir.join(BODIES).join(entry.file));
+            self.dirty = true;
+        }
+    }
+
+    /// remember where a url redirects to, if anywhere; saved with the next flush
+    pub fn set_redirect(&mut self, from: &str, to: Option<&str>) {
+        match to {
+            Some(to) => self
+                .index
+                .redirects
+                .insert(String::from(from), String::from(to)),
+            None => self.index.redirects.remove(from),
+        };
+        self.dirty = true;
+    }
+
+    /// remember which request headers a url's responses depend on; saved with the next flush
+    pub fn set_vary(&mut self, url: &str, names: &[String]) {
+        if names.is_empty() {
+            self.index.vary.remove(url);
+        } else {
+            self.index.vary.insert(String::from(url), names.to_vec());
+        }
+        self.dirty = true;
+    }
+
+    /// drop the least recently used responses until the bodies fit in the budget
+    fn evict(&mut self) {
+        let mut total: u64 = self.index.entries.values().map(|entry| entry.size).sum();
+        let mut by_use: Vec<(u64, String)> = self
+            .index
+            .entries
+            .iter()
+            .map(|(key, entry)| (entry.last_used, key.clone()))
+            .collect();
+        by_use.sort();
+        for (_, key) in by_use {
+            if total <= self.budget {
+                break;
+            }
+            if let Some(entry) = self.index.entries.remove(&key) {
+                total -= entry.size;
+                let _ = fs::remove_file(self.dir.join(BODIES).join(entry.file));
+                self.dirty = true;
+            }
+        }
+    }
+
+    /// write the index if it changed, replacing the old one all at once so it's never half-written
+    pub fn flush(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        let Ok(index) = toml::to_string(&self.index) else {
+            return;
+        };
+        let temp = self.dir.join(format!("{INDEX}.tmp"));
+        if fs::create_dir_all(&self.dir)
+            .and_then(|()| fs::write(&temp, index))
+            .is_ok()
+        {
+            self.dirty = fs::rename(temp, self.dir.join(INDEX)).is_err();
+        }
+    }
+}
+
+impl Drop for DiskCache {
+    /// save what was read and revalidated since the last write
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// pure fn to get the sha-256 of some bytes in hex
+pub fn checksum(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File},
+        time::{Duration, SystemTime},
+    };
+
+    use reqwest::header::HeaderMap;
+
+    use super::{DiskCache, BODIES, INDEX};
+    use crate::http_cache::CachePolicy;
+
+    #[test]
+    fn eviction_and_integrity() {
+        let dir = std::env::temp_dir().join(format!("nasir-disk-cache-{}", std::process::id()));
+        let policy = CachePolicy::new(&HeaderMap::new(), SystemTime::now());
+        let mut cache = DiskCache::open(dir.clone(), 10);
+        cache.write("https://a.com/", b"hello", &[], &policy);
+        cache.set_redirect("http://a.com", Some("https://a.com/"));
+        cache.write("https://b.com/\n1a2b", b"world", &[], &policy);
+        cache.write("https://huge.com/", b"far too big to fit", &[], &policy);
+        // writing a body doesn't save the index; flushing does
+        assert!(!dir.join(INDEX).exists());
+        cache.flush();
+        // reopening reads back the index that was saved
+        let mut cache = DiskCache::open(dir.clone(), 10);
+        assert_eq!(cache.index().redirects["http://a.com"], "https://a.com/");
+        // reads only touch the index in memory until it's flushed
+        let saved = fs::read_to_string(dir.join(INDEX)).unwrap();
+        assert_eq!(cache.read("https://a.com/").unwrap().0, b"hello");
+        assert_eq!(fs::read_to_string(dir.join(INDEX)).unwrap(), saved);
+        cache.flush();
+        assert_ne!(fs::read_to_string(dir.join(INDEX)).unwrap(), saved);
+        assert_eq!(cache.read("https://huge.com/"), None);
+        // the least recently used entry goes first
+        cache.write("https://c.com/", b"!", &[], &policy);
+        assert!(!cache.index.entries.contains_key("https://b.com/\n1a2b"));
+        assert_eq!(cache.index.entries.len(), 2);
+        // a body that changed on disk is thrown out
+        let file = &cache.index.entries["https://c.com/"].file;
+        fs::write(dir.join(BODIES).join(file), b"?").unwrap();
+        assert_eq!(cache.read("https://c.com/"), None);
+        // a body nothing lists is only thrown out if it's older than the index, since
+        // another Nasir might have written it and not saved its index yet
+        cache.flush();
+        let saved = fs::metadata(dir.join(INDEX)).unwrap().modified().unwrap();
+        let hour = Duration::from_secs(60 * 60);
+        for (name, written) in [("stale", saved - hour), ("fresh", saved + hour)] {
+            let path = dir.join(BODIES).join(name);
+            fs::write(&path, b"?").unwrap();
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(written)
+                .unwrap();
+        }
+        let mut cache = DiskCache::open(dir.clone(), 100);
+        assert!(!dir.join(BODIES).join("stale").exists());
+        assert!(dir.join(BODIES).join("fresh").exists());
+        // compressed bodies come back decoded
+        let zstd = zstd::encode_all(&b"squished"[..], 0).unwrap();
+        cache.write("https://z.com/", &zstd, &[String::from("zstd")], &policy);
+        assert_eq!(cache.read("https://z.com/").unwrap().0, b"squished");
+        DiskCache::clear(&dir).unwrap();
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,283 @@
+//! Saving responses Nasir can't show to files
+
+use std::{
+    ffi::OsString,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use percent_encoding::percent_decode_str;
+use url::Url;
+
+/// where downloads go unless the user says otherwise
+pub fn default_dir() -> PathBuf {
+    dirs::download_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_default()
+}
+
+/// pure fn to pick a file name for a url from the end of its path, or its host if the path is empty
+pub fn file_name(url: &str) -> String {
+    let parsed = Url::parse(url).ok();
+    let name = parsed
+        .as_ref()
+        .and_then(|url| {
+            url.path_segments()?
+                .rev()
+                .find(|segment| !segment.is_empty())
+        })
+        .map(|segment| percent_decode_str(segment).decode_utf8_lossy().into_owned())
+        .or_else(|| parsed.as_ref()?.host_str().map(String::from))
+        .unwrap_or_default();
+    clean(&name)
+}
+
+/// pure fn to make a name from a server safe to save a file as; it can't be allowed to climb out of the folder
+/// or hide itself
+fn clean(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || matches!(c, '/' | '\\' | ':') {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let name = name.trim_start_matches('.').trim();
+    if name.is_empty() {
+        String::from("download")
+    } else {
+        String::from(name)
+    }
+}
+
+/// pure fn to get the file name a `Content-Disposition` header suggests, preferring the one that can be in any language
+pub fn disposition_name(disposition: &str) -> Option<String> {
+    let mut plain = None;
+    for param in disposition.split(';').skip(1) {
+        let Some((key, value)) = param.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim().to_ascii_lowercase().as_str() {
+            // like `UTF-8''na%C3%AFve.txt`
+            "filename*" => {
+                if let Some((_, encoded)) = value.split_once("''") {
+                    return Some(clean(&percent_decode_str(encoded).decode_utf8_lossy()));
+                }
+            }
+            "filename" => plain = Some(clean(value.trim_matches('"'))),
+            _ => {}
+        }
+    }
+    plain
+}
+
+/// pure fn to check whether a response is a file to save rather than something to show, because the server
+/// says so or because it isn't text or an image
+pub fn is_attachment(content_type: Option<&str>, disposition: Option<&str>) -> bool {
+    if disposition.is_some_and(|disposition| {
+        disposition
+            .trim_start()
+            .to_ascii_lowercase()
+            .starts_with("attachment")
+    }) {
+        return true;
+    }
+    // with no type at all, whatever it is gets a chance to be shown
+    content_type.is_some_and(|content_type| {
+        !(content_type.starts_with("text/")
+            || content_type.starts_with("image/")
+            || ["html", "xml", "json", "javascript"]
+                .iter()
+                .any(|kind| content_type.contains(kind)))
+    })
+}
+
+/// pure fn to work out where to save a file from what the user typed: nothing means `name` in `default_dir`,
+/// and a folder means `name` in that folder
+pub fn destination(input: &str, default_dir: &Path, name: &str) -> PathBuf {
+    let input = input.trim();
+    if input.is_empty() {
+        return default_dir.join(name);
+    }
+    let path = input.strip_prefix("~/").map_or_else(
+        || PathBuf::from(input),
+        |rest| dirs::home_dir().unwrap_or_default().join(rest),
+    );
+    if input.ends_with(std::path::MAIN_SEPARATOR) || path.is_dir() {
+        path.join(name)
+    } else {
+        path
+    }
+}
+
+/// the first of a path and its numbered copies that nothing's using yet
+pub fn unused(path: &Path) -> PathBuf {
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let name = path.file_name().map_or_else(
+        || String::from("download"),
+        |name| name.to_string_lossy().into_owned(),
+    );
+    for copy in 0.. {
+        let path = dir.join(numbered(&name, copy));
+        if !path.exists() && !part_path(&path).exists() {
+            return path;
+        }
+    }
+    unreachable!("there's always another number to try")
+}
+
+/// pure fn to get where a file is written until all of it's there
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut part = OsString::from(path.as_os_str());
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+/// how a download is going
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum State {
+    Going,
+    Done,
+    Failed(String),
+    Cancelled,
+}
+
+/// a file being saved in the background, as `about:downloads` lists it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Download {
+    pub url: String,
+    /// where the file goes once all of it's there
+    pub path: PathBuf,
+    /// bytes written so far, including any from before it was resumed
+    pub received: u64,
+    pub total: Option<u64>,
+    /// the response's `ETag` or `Last-Modified`, so resuming only gets the rest of the same file
+    pub validator: Option<String>,
+    pub state: State,
+}
+
+impl Download {
+    pub const fn new(url: String, path: PathBuf) -> Self {
+        Self {
+            url,
+            path,
+            received: 0,
+            total: None,
+            validator: None,
+            state: State::Going,
+        }
+    }
+
+    /// pure fn to check whether it stopped partway, so the rest can be fetched
+    pub const fn can_resume(&self) -> bool {
+        matches!(self.state, State::Failed(_) | State::Cancelled)
+    }
+}
+
+/// write a file into a folder without replacing anything, adding a number to the name if it's taken
+pub fn save(dir: &Path, name: &str, bytes: &[u8]) -> Result<PathBuf, String> {
+    fs::create_dir_all(dir).map_err(|err| format!("Couldn't create {}: {err}", dir.display()))?;
+    for copy in 0.. {
+        let path = dir.join(numbered(name, copy));
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                return file
+                    .write_all(bytes)
+                    .map(|()| path.clone())
+                    .map_err(|err| format!("Couldn't write {}: {err}", path.display()))
+            }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(format!("Couldn't create {}: {err}", path.display())),
+        }
+    }
+    unreachable!("there's always another number to try")
+}
+
+/// pure fn to get the name of the nth copy of a file, like `notes (2).txt`
+fn numbered(name: &str, copy: usize) -> String {
+    if copy == 0 {
+        return String::from(name);
+    }
+    match name.rsplit_once('.').filter(|(stem, _)| !stem.is_empty()) {
+        Some((stem, extension)) => format!("{stem} ({copy}).{extension}"),
+        None => format!("{name} ({copy})"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use std::path::Path;
+
+    use super::{destination, disposition_name, file_name, is_attachment, save, unused};
+
+    #[test]
+    fn files() {
+        assert_eq!(file_name("https://example.com/files/a%20b.zip"), "a b.zip");
+        assert_eq!(file_name("https://example.com/files/"), "files");
+        assert_eq!(file_name("https://example.com"), "example.com");
+        assert_eq!(file_name("gopher://example.org/9/..%2F.hidden"), "_.hidden");
+        assert_eq!(file_name("data:,hi"), "download");
+        let dir = std::env::temp_dir().join(format!("nasir-download-{}", std::process::id()));
+        let first = save(&dir, "notes.txt", b"one").unwrap();
+        let second = save(&dir, "notes.txt", b"two").unwrap();
+        assert_eq!(second.file_name().unwrap(), "notes (1).txt");
+        assert_eq!(fs::read(first).unwrap(), b"one");
+        assert_eq!(
+            save(&dir, "README", b"").unwrap().file_name().unwrap(),
+            "README"
+        );
+        assert_eq!(
+            save(&dir, "README", b"").unwrap().file_name().unwrap(),
+            "README (1)"
+        );
+        assert_eq!(
+            unused(&dir.join("notes.txt")).file_name().unwrap(),
+            "notes (2).txt"
+        );
+        assert_eq!(destination("", &dir, "a.zip"), dir.join("a.zip"));
+        assert_eq!(
+            destination(
+                &format!("{}", dir.display()),
+                Path::new("/nowhere"),
+                "a.zip"
+            ),
+            dir.join("a.zip")
+        );
+        assert_eq!(
+            destination("/tmp/b.zip", &dir, "a.zip"),
+            Path::new("/tmp/b.zip")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn attachments() {
+        assert!(is_attachment(Some("application/zip"), None));
+        assert!(is_attachment(
+            Some("text/plain"),
+            Some("attachment; filename=\"a.txt\"")
+        ));
+        assert!(!is_attachment(Some("text/html"), Some("inline")));
+        assert!(!is_attachment(Some("application/xhtml+xml"), None));
+        assert!(!is_attachment(Some("image/webp"), None));
+        assert!(!is_attachment(None, None));
+        assert_eq!(
+            disposition_name("attachment; filename=\"report.pdf\""),
+            Some(String::from("report.pdf"))
+        );
+        assert_eq!(
+            disposition_name(
+                "attachment; filename=\"fallback.txt\"; filename*=UTF-8''na%C3%AFve%2F.txt"
+            ),
+            Some(String::from("naïve_.txt"))
+        );
+        assert_eq!(disposition_name("attachment"), None);
+    }
+}
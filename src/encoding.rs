@@ -0,0 +1,156 @@
+//! Compressed response bodies, following the `Content-Encoding` header
+
+use std::io::Read;
+
+use flate2::read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+use reqwest::header::{HeaderMap, CONTENT_ENCODING};
+
+use crate::utils::format_bytes;
+
+/// every content coding Nasir can decode, best first, to send as `Accept-Encoding`
+pub const ACCEPT_ENCODING: &str = "zstd, br, gzip, deflate";
+
+/// the most a body can grow to once it's decoded or as it's read, so a few compressed kilobytes
+/// or a server that never stops sending can't fill up memory
+pub const MAX_BODY: usize = 256 * 1024 * 1024;
+
+/// pure fn to get the content codings a response's body went through, in the order they were applied
+pub fn content_encoding(headers: &HeaderMap) -> Vec<String> {
+    headers
+        .get_all(CONTENT_ENCODING)
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .flat_map(|header| header.split(','))
+        .map(|coding| coding.trim().to_lowercase())
+        .filter(|coding| !coding.is_empty() && coding != "identity")
+        .collect()
+}
+
+/// pure fn to undo the content codings a body went through
+pub fn decode(bytes: &[u8], codings: &[String]) -> Result<Vec<u8>, String> {
+    decode_within(bytes, codings, MAX_BODY)
+}
+
+/// pure fn to undo the content codings a body went through, giving up if it gets bigger than `limit`
+fn decode_within(bytes: &[u8], codings: &[String], limit: usize) -> Result<Vec<u8>, String> {
+    let mut body = bytes.to_vec();
+    // the last coding applied is the first to take off
+    for coding in codings.iter().rev() {
+        body = decode_one(&body, coding, limit)
+            .map_err(|err| format!("Couldn't decode {coding} body: {err}"))?;
+    }
+    Ok(body)
+}
+
+/// pure fn to undo a single content coding
+fn decode_one(bytes: &[u8], coding: &str, limit: usize) -> Result<Vec<u8>, String> {
+    let mut body = Vec::new();
+    // reading one byte past the limit is enough to tell it's too big
+    let read = |decoder: &mut dyn Read, body: &mut Vec<u8>| {
+        decoder.take(limit as u64 + 1).read_to_end(body)
+    };
+    match coding {
+        "gzip" | "x-gzip" => read(&mut MultiGzDecoder::new(bytes), &mut body),
+        // `deflate` is meant to be zlib-wrapped, but some servers send the raw stream
+        "deflate" => read(&mut ZlibDecoder::new(bytes), &mut body).or_else(|_| {
+            body.clear();
+            read(&mut DeflateDecoder::new(bytes), &mut body)
+        }),
+        "br" => read(&mut brotli::Decompressor::new(bytes, 4096), &mut body),
+        "zstd" => zstd::stream::read::Decoder::new(bytes)
+            .and_then(|mut decoder| read(&mut decoder, &mut body)),
+        _ => return Err(String::from("unsupported content encoding")),
+    }
+    .map_err(|err| err.to_string())?;
+    if body.len() > limit {
+        return Err(format!("it's over {} decoded", format_bytes(limit)));
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{
+        write::{DeflateEncoder, GzEncoder, ZlibEncoder},
+        Compression,
+    };
+    use reqwest::header::{HeaderMap, HeaderValue, CONTENT_ENCODING};
+
+    use super::{content_encoding, decode, decode_within};
+
+    const BODY: &[u8] =
+        b"<p>the same paragraph again and again</p><p>the same paragraph again and again</p>";
+
+    fn codings(codings: &[&str]) -> Vec<String> {
+        codings.iter().map(|&coding| String::from(coding)).collect()
+    }
+
+    #[test]
+    fn decoding() {
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(BODY).unwrap();
+        let gzip = gzip.finish().unwrap();
+        assert_eq!(decode(&gzip, &codings(&["gzip"])).unwrap(), BODY);
+        let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+        zlib.write_all(BODY).unwrap();
+        assert_eq!(
+            decode(&zlib.finish().unwrap(), &codings(&["deflate"])).unwrap(),
+            BODY
+        );
+        let mut raw = DeflateEncoder::new(Vec::new(), Compression::default());
+        raw.write_all(BODY).unwrap();
+        assert_eq!(
+            decode(&raw.finish().unwrap(), &codings(&["deflate"])).unwrap(),
+            BODY
+        );
+        let mut br = Vec::new();
+        brotli::CompressorWriter::new(&mut br, 4096, 5, 22)
+            .write_all(BODY)
+            .unwrap();
+        assert_eq!(decode(&br, &codings(&["br"])).unwrap(), BODY);
+        let zstd = zstd::encode_all(BODY, 0).unwrap();
+        assert_eq!(decode(&zstd, &codings(&["zstd"])).unwrap(), BODY);
+        // codings stack, and come off in reverse
+        let stacked = zstd::encode_all(gzip.as_slice(), 0).unwrap();
+        assert_eq!(decode(&stacked, &codings(&["gzip", "zstd"])).unwrap(), BODY);
+        assert!(decode(BODY, &codings(&["compress"])).is_err());
+        assert!(decode(BODY, &codings(&["gzip"])).is_err());
+    }
+
+    #[test]
+    fn bombs() {
+        // a megabyte of zeros squashes down to almost nothing
+        let zeros = vec![0; 1024 * 1024];
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::best());
+        gzip.write_all(&zeros).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let mut br = Vec::new();
+        brotli::CompressorWriter::new(&mut br, 4096, 5, 22)
+            .write_all(&zeros)
+            .unwrap();
+        let zstd = zstd::encode_all(zeros.as_slice(), 0).unwrap();
+        for (body, coding) in [(&gzip, "gzip"), (&br, "br"), (&zstd, "zstd")] {
+            assert!(body.len() < 10 * 1024);
+            assert!(decode_within(body, &codings(&[coding]), 64 * 1024).is_err());
+            assert_eq!(
+                decode_within(body, &codings(&[coding]), zeros.len()).unwrap(),
+                zeros
+            );
+        }
+        let mut zlib = ZlibEncoder::new(Vec::new(), Compression::best());
+        zlib.write_all(&zeros).unwrap();
+        assert!(decode_within(&zlib.finish().unwrap(), &codings(&["deflate"]), 64 * 1024).is_err());
+    }
+
+    #[test]
+    fn headers() {
+        let mut headers = HeaderMap::new();
+        assert!(content_encoding(&headers).is_empty());
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static("Identity"));
+        assert!(content_encoding(&headers).is_empty());
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip, BR"));
+        assert_eq!(content_encoding(&headers), ["gzip", "br"]);
+    }
+}
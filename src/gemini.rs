@@ -0,0 +1,454 @@
+//! The Gemini protocol: one request line over TLS, a status line back, then the body
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs,
+    io::{self, Read, Write},
+    net::TcpStream,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crossterm::terminal::SetTitle;
+use native_tls::{Identity, TlsConnector, TlsStream};
+use url::Url;
+
+use crate::{
+    cacher::ByteCacher,
+    config::ClientIdentity,
+    encoding::MAX_BODY,
+    tls::{self, CertificateInfo},
+    types::prelude::*,
+};
+
+pub const DEFAULT_PORT: u16 = 1965;
+
+/// the longest status line a server is allowed to send, including the `\r\n`
+const MAX_HEADER: usize = 1029;
+
+/// what a server said about a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    /// two digits; the first says what kind of answer it is
+    pub status: u8,
+    /// a mime type for success, a url for redirects, and a prompt or explanation for everything else
+    pub meta: String,
+    pub body: Vec<u8>,
+}
+
+/// a connection that's finished its handshake but hasn't sent anything yet, so the certificate can be checked first
+pub struct Connection {
+    stream: TlsStream<TcpStream>,
+    /// the certificate the server presented, which gets pinned rather than checked against roots
+    pub certificate: CertificateInfo,
+}
+
+/// read a client certificate for capsules that ask for one
+pub fn identity(identity: &ClientIdentity) -> Result<Identity, String> {
+    Identity::from_pkcs8(
+        &tls::read(&identity.certificate)?,
+        &tls::read(&identity.key)?,
+    )
+    .map_err(|err| {
+        format!(
+            "Certificate Error in {}: {err}",
+            identity.certificate.display()
+        )
+    })
+}
+
+/// finish the handshake with a capsule over a connection to it, without sending the request
+pub fn connect(
+    url: &Url,
+    stream: TcpStream,
+    identity: Option<Identity>,
+) -> Result<Connection, String> {
+    let host = url.host_str().ok_or_else(|| format!("No host in {url}"))?;
+    // most capsules use self-signed certificates, so trust comes from pinning instead of roots
+    let mut connector = TlsConnector::builder();
+    connector
+        .danger_accept_invalid_certs(true)
+        .danger_accept_invalid_hostnames(true);
+    if let Some(identity) = identity {
+        connector.identity(identity);
+    }
+    let stream = connector
+        .build()
+        .and_then(|connector| {
+            connector.connect(host, stream).map_err(|err| match err {
+                native_tls::HandshakeError::Failure(err) => err,
+                native_tls::HandshakeError::WouldBlock(_) => unreachable!("the stream blocks"),
+            })
+        })
+        .map_err(|err| err.to_string())?;
+    let certificate = stream
+        .peer_certificate()
+        .ok()
+        .flatten()
+        .and_then(|cert| cert.to_der().ok())
+        .map(|der| tls::describe(&der))
+        .ok_or_else(|| format!("{host} didn't present a certificate"))?;
+    Ok(Connection {
+        stream,
+        certificate,
+    })
+}
+
+/// send a request and read the whole response, calling `on_read` with the size of each piece of the body.
+/// The body has to arrive within `timeout`, so a capsule that never stops sending can't hold on forever
+pub fn request(
+    mut connection: Connection,
+    url: &Url,
+    timeout: Duration,
+    on_read: impl Fn(usize),
+) -> Result<Response, String> {
+    let deadline = Instant::now() + timeout;
+    let stream = &mut connection.stream;
+    let mut target = url.clone();
+    target.set_fragment(None);
+    stream
+        .write_all(format!("{target}\r\n").as_bytes())
+        .map_err(|err| err.to_string())?;
+    let mut received = Vec::new();
+    let mut buf = [0; 8192];
+    let header_end = loop {
+        if let Some(end) = received.windows(2).position(|pair| pair == b"\r\n") {
+            break end;
+        }
+        if received.len() > MAX_HEADER {
+            return Err(String::from("The server's status line is too long"));
+        }
+        match read_some(stream, &mut buf)? {
+            0 => {
+                return Err(String::from(
+                    "The server closed the connection without answering",
+                ))
+            }
+            read => received.extend_from_slice(&buf[..read]),
+        }
+    };
+    let (status, meta) = parse_header(&String::from_utf8_lossy(&received[..header_end]))?;
+    let mut body = received.split_off(header_end + 2);
+    on_read(body.len());
+    // only successful responses have bodies
+    if status / 10 == 2 {
+        read_body(stream, &mut body, MAX_BODY, deadline, on_read)?;
+    } else {
+        body.clear();
+    }
+    Ok(Response { status, meta, body })
+}
+
+/// read the rest of a body until the server hangs up, giving up if it gets bigger than `limit` or runs past `deadline`
+pub fn read_body(
+    stream: &mut impl Read,
+    body: &mut Vec<u8>,
+    limit: usize,
+    deadline: Instant,
+    on_read: impl Fn(usize),
+) -> Result<(), String> {
+    let mut buf = [0; 8192];
+    loop {
+        if body.len() > limit {
+            return Err(format!("The page is bigger than {} MB", limit >> 20));
+        }
+        if Instant::now() >= deadline {
+            return Err(String::from("The server took too long to send the page"));
+        }
+        match read_some(stream, &mut buf)? {
+            0 => return Ok(()),
+            read => {
+                on_read(read);
+                body.extend_from_slice(&buf[..read]);
+            }
+        }
+    }
+}
+
+/// read what's there; lots of servers hang up without closing TLS properly, which is just the end of the body
+fn read_some(stream: &mut impl Read, buf: &mut [u8]) -> Result<usize, String> {
+    match stream.read(buf) {
+        Ok(read) => Ok(read),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(0),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// pure fn to read a status line like `20 text/gemini`
+pub fn parse_header(line: &str) -> Result<(u8, String), String> {
+    let (status, meta) = line.split_once([' ', '\t']).unwrap_or((line, ""));
+    match status.parse() {
+        Ok(status) if (10..70).contains(&status) => Ok((status, String::from(meta.trim()))),
+        _ => Err(format!("Not a Gemini status line: `{line}`")),
+    }
+}
+
+/// the certificate each capsule presented the first time, so a different one later is noticed
+pub struct KnownHosts {
+    path: Option<PathBuf>,
+    /// sha-256 fingerprints by `host:port`
+    hosts: BTreeMap<String, String>,
+}
+
+impl KnownHosts {
+    /// read pinned certificates from a file, or keep them in memory if there isn't one
+    pub fn open(path: Option<PathBuf>) -> Self {
+        let hosts = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|file| {
+                file.lines()
+                    .filter_map(|line| line.split_once(' '))
+                    .map(|(host, fingerprint)| {
+                        (String::from(host), String::from(fingerprint.trim()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { path, hosts }
+    }
+
+    /// check a capsule's certificate against the one pinned for it, pinning it if it's the first one seen
+    /// or if `replace` says the user chose to trust the new one; returns the pinned fingerprint if it's different
+    pub fn check(&mut self, host: &str, fingerprint: &str, replace: bool) -> Result<(), String> {
+        match self.hosts.get(host) {
+            Some(pinned) if pinned == fingerprint => return Ok(()),
+            Some(pinned) if !replace => return Err(pinned.clone()),
+            _ => {}
+        }
+        self.hosts
+            .insert(String::from(host), String::from(fingerprint));
+        self.save();
+        Ok(())
+    }
+
+    /// write every pinned certificate to the file
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let mut file = String::new();
+        for (host, fingerprint) in &self.hosts {
+            let _ = writeln!(file, "{host} {fingerprint}");
+        }
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = fs::write(path, file);
+    }
+}
+
+/// one line of a gemtext document
+#[derive(Debug, PartialEq, Eq)]
+pub enum Line<'a> {
+    Text(&'a str),
+    Link {
+        url: &'a str,
+        label: Option<&'a str>,
+    },
+    Heading(u8, &'a str),
+    Item(&'a str),
+    Quote(&'a str),
+    /// the lines between two ```` ``` ```` lines, shown as they are
+    Preformatted(Vec<&'a str>),
+}
+
+/// pure fn to split a gemtext document into its lines
+pub fn parse(body: &str) -> Vec<Line<'_>> {
+    let mut lines = Vec::new();
+    let mut preformatted: Option<Vec<&str>> = None;
+    for line in body.lines() {
+        if line.starts_with("```") {
+            match preformatted.take() {
+                Some(block) => lines.push(Line::Preformatted(block)),
+                None => preformatted = Some(Vec::new()),
+            }
+            continue;
+        }
+        if let Some(block) = &mut preformatted {
+            block.push(line);
+            continue;
+        }
+        lines.push(parse_line(line));
+    }
+    // a block that's never closed runs to the end of the document
+    if let Some(block) = preformatted {
+        lines.push(Line::Preformatted(block));
+    }
+    lines
+}
+
+/// pure fn to read a line of gemtext outside a preformatted block
+fn parse_line(line: &str) -> Line<'_> {
+    if let Some(target) = line.strip_prefix("=>") {
+        let target = target.trim();
+        let (url, label) = target
+            .split_once(char::is_whitespace)
+            .map_or((target, None), |(url, label)| (url, Some(label.trim())));
+        return Line::Link { url, label };
+    }
+    for (level, prefix) in [(3, "###"), (2, "##"), (1, "#")] {
+        if let Some(heading) = line.strip_prefix(prefix) {
+            return Line::Heading(level, heading.trim());
+        }
+    }
+    if let Some(item) = line.strip_prefix("* ") {
+        return Line::Item(item.trim());
+    }
+    line.strip_prefix('>')
+        .map_or(Line::Text(line), |quote| Line::Quote(quote.trim()))
+}
+
+/// display a gemtext document with the same styles as the html it stands in for
+pub fn render(
+    body: &str,
+    url: &str,
+    set_title: &mut SetTitle<RStr>,
+    cacher: &Arc<Mutex<ByteCacher>>,
+    forms: &mut FormState,
+    verbose: bool,
+) -> Vec<TerminalLine> {
+    let element = |name: &str, text: &str| DocElement::HtmlElement {
+        name: name.into(),
+        children: vec![DocElement::Text(text.into())],
+        properties: BTreeMap::new(),
+    };
+    let mut titled = false;
+    let mut lines = Vec::new();
+    for line in parse(body) {
+        let element = match line {
+            Line::Text("") => {
+                lines.push(TerminalLine::from(""));
+                continue;
+            }
+            Line::Text(text) => DocElement::Text(text.into()),
+            Line::Link { url, label } => DocElement::HtmlElement {
+                name: "a".into(),
+                children: vec![DocElement::Text(label.unwrap_or(url).into())],
+                properties: BTreeMap::from([("href".into(), url.into())]),
+            },
+            Line::Heading(level, text) => {
+                if !titled {
+                    set_title.0 = format!("{text} - Nasir").into();
+                    titled = true;
+                }
+                element(&format!("h{level}"), text)
+            }
+            Line::Item(item) => DocElement::Text(format!("• {item}").into()),
+            Line::Quote(quote) => {
+                lines.extend(
+                    element("i", quote)
+                        .display(set_title, cacher, url, TextDirection::Auto, forms, verbose)
+                        .into_iter()
+                        .map(|tl| tl.map(|rstr| format!("│ {rstr}").into())),
+                );
+                continue;
+            }
+            Line::Preformatted(block) => {
+                lines.extend(preformatted(&block));
+                continue;
+            }
+        };
+        lines.extend(element.display(set_title, cacher, url, TextDirection::Auto, forms, verbose));
+    }
+    lines
+}
+
+/// pure fn to show a preformatted block without wrapping it, in the colors of a code block
+fn preformatted(block: &[&str]) -> Vec<TerminalLine> {
+    let lines: Vec<TerminalLine> = block.iter().map(|&line| TerminalLine::from(line)).collect();
+    let width = lines
+        .iter()
+        .map(TerminalLine::max_visible_length)
+        .max()
+        .unwrap_or(0);
+    lines
+        .into_iter()
+        .map(|tl| {
+            tl.visible_right_pad(width)
+                .map(|rstr| format!("\x1b[38;5;250;48;5;240m{rstr}\x1b[0m").into())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs, io,
+        time::{Duration, Instant},
+    };
+
+    use super::{parse, parse_header, read_body, KnownHosts, Line};
+
+    #[test]
+    fn gemtext() {
+        let doc = "# Title\n=> gemini://example.org/ Example\n=>/relative\n* item\n>quote\n```alt\n  * not an item\n```\nplain\n## Sub\n```\nnever closed";
+        assert_eq!(
+            parse(doc),
+            [
+                Line::Heading(1, "Title"),
+                Line::Link {
+                    url: "gemini://example.org/",
+                    label: Some("Example")
+                },
+                Line::Link {
+                    url: "/relative",
+                    label: None
+                },
+                Line::Item("item"),
+                Line::Quote("quote"),
+                Line::Preformatted(vec!["  * not an item"]),
+                Line::Text("plain"),
+                Line::Heading(2, "Sub"),
+                Line::Preformatted(vec!["never closed"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn protocol() {
+        assert_eq!(
+            parse_header("20 text/gemini; lang=en"),
+            Ok((20, String::from("text/gemini; lang=en")))
+        );
+        assert_eq!(
+            parse_header("31\tgemini://a/"),
+            Ok((31, String::from("gemini://a/")))
+        );
+        assert_eq!(parse_header("10"), Ok((10, String::new())));
+        assert!(parse_header("HTTP/1.1 200 OK").is_err());
+        // a body ends when the server hangs up, unless it's too big or too slow first
+        let later = Instant::now() + Duration::from_secs(60);
+        let mut body = Vec::from(*b"# ");
+        read_body(&mut &b"Hi"[..], &mut body, 1 << 20, later, |_| {}).unwrap();
+        assert_eq!(body, b"# Hi");
+        let mut body = Vec::new();
+        assert!(read_body(&mut io::repeat(b'a'), &mut body, 1 << 20, later, |_| {}).is_err());
+        assert!(body.len() <= (1 << 20) + 8192);
+        let mut body = Vec::new();
+        assert!(read_body(
+            &mut io::repeat(b'a'),
+            &mut body,
+            1 << 20,
+            Instant::now(),
+            |_| {}
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn pinning() {
+        let path = std::env::temp_dir().join(format!("nasir-gemini-{}", std::process::id()));
+        let mut hosts = KnownHosts::open(Some(path.clone()));
+        assert_eq!(hosts.check("a:1965", "AA", false), Ok(()));
+        assert_eq!(hosts.check("a:1965", "AA", false), Ok(()));
+        // pins last between sessions
+        let mut hosts = KnownHosts::open(Some(path.clone()));
+        assert_eq!(hosts.check("a:1965", "BB", false), Err(String::from("AA")));
+        assert_eq!(hosts.check("a:1965", "BB", true), Ok(()));
+        assert_eq!(hosts.check("a:1965", "BB", false), Ok(()));
+        fs::remove_file(path).unwrap();
+    }
+}
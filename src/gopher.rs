@@ -0,0 +1,368 @@
+//! The Gopher protocol: a selector over plain TCP, and menus of typed items that point at more selectors
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use url::Url;
+
+use crate::{encoding::MAX_BODY, gemini::read_body, types::prelude::*};
+
+pub const DEFAULT_PORT: u16 = 70;
+
+/// the content type Nasir gives menus, so they're drawn as lists of items
+pub const MENU: &str = "application/gopher-menu";
+
+/// characters that can't go in a url's path as they are
+const PATH: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// what a gopher url asks for
+#[derive(Debug, PartialEq, Eq)]
+pub struct Request {
+    pub host: String,
+    pub port: u16,
+    /// the item type, which says what the response will be
+    pub kind: char,
+    pub selector: String,
+    /// what to search for, for type 7 items
+    pub search: Option<String>,
+}
+
+/// pure fn to read a `gopher://host:port/<type><selector>` url; a search goes after a tab or in the query
+pub fn parse_url(url: &str) -> Result<Request, String> {
+    let parsed = Url::parse(url).map_err(|err| format!("{err} ({url})"))?;
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| format!("No host in {url}"))?;
+    let path = percent_decode_str(parsed.path()).decode_utf8_lossy();
+    let mut chars = path.strip_prefix('/').unwrap_or(&path).chars();
+    // the top of a server is a menu
+    let kind = chars.next().unwrap_or('1');
+    let rest = chars.as_str();
+    let query = parsed
+        .query()
+        .map(|query| percent_decode_str(query).decode_utf8_lossy().into_owned());
+    let (selector, search) = match rest.split_once('\t') {
+        Some((selector, search)) => (String::from(selector), Some(String::from(search))),
+        // only searches have queries; anywhere else a `?` is just part of the selector
+        None if kind == '7' => (String::from(rest), query),
+        None => (
+            query.map_or_else(|| String::from(rest), |query| format!("{rest}?{query}")),
+            None,
+        ),
+    };
+    // the request is one line with a tab before the search, so anything else that ends or splits it could
+    // sneak more onto it
+    if selector.contains(['\r', '\n', '\0', '\t']) {
+        return Err(format!(
+            "Gopher selectors can't have line breaks, tabs or nulls ({url})"
+        ));
+    }
+    if search
+        .as_ref()
+        .is_some_and(|search| search.contains(['\r', '\n', '\0']))
+    {
+        return Err(format!(
+            "Gopher searches can't have line breaks or nulls ({url})"
+        ));
+    }
+    Ok(Request {
+        host: String::from(host),
+        port: parsed.port().unwrap_or(DEFAULT_PORT),
+        kind,
+        selector,
+        search,
+    })
+}
+
+/// pure fn to get the content type of an item type, as far as Nasir cares
+pub const fn content_type(kind: char) -> Option<&'static str> {
+    match kind {
+        '1' | '7' => Some(MENU),
+        '0' => Some("text/plain"),
+        'h' => Some("text/html"),
+        'g' => Some("image/gif"),
+        'p' => Some("image/png"),
+        // the image crate can work out what other images are
+        'I' => None,
+        _ => Some("application/octet-stream"),
+    }
+}
+
+/// send a selector over a connection to the server and read everything it sends back, calling `on_read` with
+/// the size of each piece. Everything has to arrive within `timeout`, so a server that never stops sending
+/// can't hold on forever
+pub fn request(
+    request: &Request,
+    stream: TcpStream,
+    timeout: Duration,
+    on_read: impl Fn(usize),
+) -> Result<Vec<u8>, String> {
+    exchange(request, stream, MAX_BODY, Instant::now() + timeout, on_read)
+}
+
+/// send the selector and read the response, giving up if it gets bigger than `limit` or runs past `deadline`
+fn exchange(
+    request: &Request,
+    mut stream: impl Read + Write,
+    limit: usize,
+    deadline: Instant,
+    on_read: impl Fn(usize),
+) -> Result<Vec<u8>, String> {
+    // a search goes after the selector, separated by a tab
+    let line = request.search.as_ref().map_or_else(
+        || format!("{}\r\n", request.selector),
+        |search| format!("{}\t{search}\r\n", request.selector),
+    );
+    stream
+        .write_all(line.as_bytes())
+        .map_err(|err| err.to_string())?;
+    let mut body = Vec::new();
+    read_body(&mut stream, &mut body, limit, deadline, on_read)?;
+    if matches!(request.kind, '0' | '1' | '7') {
+        body = unescape(&body);
+    }
+    Ok(body)
+}
+
+/// pure fn to take the `.` line off the end of a text response, and the extra `.` off lines that start with one
+fn unescape(body: &[u8]) -> Vec<u8> {
+    let text = String::from_utf8_lossy(body);
+    let mut lines: Vec<&str> = text.lines().collect();
+    if lines.last() == Some(&".") {
+        lines.pop();
+    }
+    lines
+        .iter()
+        .map(|line| {
+            line.strip_prefix("..")
+                .map_or_else(|| String::from(*line), |line| format!(".{line}"))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .into_bytes()
+}
+
+/// one line of a menu
+#[derive(Debug, PartialEq, Eq)]
+pub struct Item<'a> {
+    pub kind: char,
+    pub display: &'a str,
+    pub selector: &'a str,
+    pub host: &'a str,
+    pub port: &'a str,
+}
+
+impl Item<'_> {
+    /// pure fn to get where the item goes, if it goes anywhere Nasir can follow
+    pub fn url(&self) -> Option<String> {
+        match self.kind {
+            'i' | '3' | '8' | 'T' | '+' => None,
+            // links off gopher hide the url in the selector
+            'h' if self.selector.starts_with("URL:") => Some(String::from(&self.selector[4..])),
+            kind => {
+                let port = match self.port {
+                    "" | "70" => String::new(),
+                    port => format!(":{port}"),
+                };
+                Some(format!(
+                    "gopher://{}{port}/{kind}{}",
+                    self.host,
+                    utf8_percent_encode(self.selector, PATH)
+                ))
+            }
+        }
+    }
+
+    /// pure fn to get the tag that says what kind of item it is
+    pub const fn indicator(&self) -> &'static str {
+        match self.kind {
+            '0' => "[TXT]",
+            '1' => "[DIR]",
+            '3' => "[ERR]",
+            '7' => "[ASK]",
+            '8' | 'T' => "[TEL]",
+            'g' | 'I' | 'p' => "[IMG]",
+            'h' => "[WEB]",
+            's' => "[SND]",
+            '4' | '5' | '6' | '9' | 'd' => "[BIN]",
+            'i' => "     ",
+            _ => "[???]",
+        }
+    }
+}
+
+/// pure fn to read the items of a menu
+pub fn parse_menu(body: &str) -> Vec<Item<'_>> {
+    body.lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let mut chars = line.chars();
+            let kind = chars.next().unwrap_or('i');
+            let mut fields = chars.as_str().split('\t');
+            let display = fields.next().unwrap_or_default();
+            // lines without the other fields are just text, whatever they start with
+            let (Some(selector), Some(host)) = (fields.next(), fields.next()) else {
+                return Item {
+                    kind: 'i',
+                    display: line,
+                    selector: "",
+                    host: "",
+                    port: "",
+                };
+            };
+            Item {
+                kind,
+                display,
+                selector,
+                host,
+                port: fields.next().unwrap_or_default().trim(),
+            }
+        })
+        .collect()
+}
+
+/// pure fn to draw a menu, with the items that go somewhere as links
+pub fn render_menu(body: &str) -> Vec<TerminalLine> {
+    parse_menu(body)
+        .iter()
+        .map(|item| {
+            let indicator = item.indicator();
+            let line = TerminalLine::from(item.display);
+            match item.url() {
+                Some(url) => line
+                    .into_link(url.into())
+                    .map(|rstr| format!("{indicator} {rstr}").into()),
+                None if item.kind == '3' => {
+                    line.map(|rstr| format!("{indicator} \x1b[91m{rstr}\x1b[0m").into())
+                }
+                None => line.map(|rstr| format!("{indicator} {rstr}").into()),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{self, Read, Write},
+        time::{Duration, Instant},
+    };
+
+    use super::{exchange, parse_menu, parse_url, unescape, Item, Request};
+
+    /// a server that answers with as much `text` as it's asked for, forever
+    struct Endless<'a> {
+        text: &'a [u8],
+        sent: Vec<u8>,
+    }
+
+    impl Read for Endless<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.text.repeat(buf.len()).as_slice().read(buf)
+        }
+    }
+
+    impl Write for Endless<'_> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.sent.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn urls() {
+        assert_eq!(
+            parse_url("gopher://example.org").unwrap(),
+            Request {
+                host: String::from("example.org"),
+                port: 70,
+                kind: '1',
+                selector: String::new(),
+                search: None
+            }
+        );
+        let search = parse_url("gopher://example.org:7070/7/find%09cats").unwrap();
+        assert_eq!((search.kind, search.port), ('7', 7070));
+        assert_eq!(
+            (search.selector.as_str(), search.search.as_deref()),
+            ("/find", Some("cats"))
+        );
+        let search = parse_url("gopher://example.org/7/find?cats%20%26%20dogs").unwrap();
+        assert_eq!(search.search.as_deref(), Some("cats & dogs"));
+        let text = parse_url("gopher://example.org/0/about.txt?v=2").unwrap();
+        assert_eq!(
+            (text.selector.as_str(), text.search),
+            ("/about.txt?v=2", None)
+        );
+        assert!(parse_url("gopher://example.org/0/a%0D%0Ab").is_err());
+        assert!(parse_url("gopher://example.org/0/a%00").is_err());
+        assert!(parse_url("gopher://example.org/0/a?b%09c").is_err());
+        assert!(parse_url("gopher://example.org/7/find?cats%0D%0A/secret").is_err());
+    }
+
+    #[test]
+    fn menus() {
+        let menu = "iWelcome!\t\terror.host\t1\r\n1Phlog\t/phlog\texample.org\t70\r\n7Search\t/find\texample.org\t7070\r\nhWeb\tURL:https://example.com/\texample.org\t70\r\n0Read me\t/read me?.txt\texample.org\t70\r\nnot a real item\r\n";
+        let items = parse_menu(menu);
+        assert_eq!(
+            items[1],
+            Item {
+                kind: '1',
+                display: "Phlog",
+                selector: "/phlog",
+                host: "example.org",
+                port: "70"
+            }
+        );
+        let urls: Vec<Option<String>> = items.iter().map(Item::url).collect();
+        assert_eq!(
+            urls,
+            [
+                None,
+                Some(String::from("gopher://example.org/1/phlog")),
+                Some(String::from("gopher://example.org:7070/7/find")),
+                Some(String::from("https://example.com/")),
+                Some(String::from("gopher://example.org/0/read%20me%3F.txt")),
+                None,
+            ]
+        );
+        // the selector that went into the url comes back out of it
+        assert_eq!(
+            parse_url(urls[4].as_deref().unwrap()).unwrap().selector,
+            "/read me?.txt"
+        );
+        assert_eq!(items[5].display, "not a real item");
+        assert_eq!(unescape(b"..hidden\r\nline\r\n.\r\n"), b".hidden\nline");
+    }
+
+    #[test]
+    fn endless_bodies() {
+        let request = parse_url("gopher://example.org/9/big.iso").unwrap();
+        let later = Instant::now() + Duration::from_secs(60);
+        let mut server = Endless {
+            text: b"a",
+            sent: Vec::new(),
+        };
+        let err = exchange(&request, &mut server, 1 << 20, later, |_| {}).unwrap_err();
+        assert_eq!(err, "The page is bigger than 1 MB");
+        assert_eq!(server.sent, b"/big.iso\r\n");
+        assert!(exchange(&request, &mut server, 1 << 20, Instant::now(), |_| {}).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+//! Pages that were visited, saved between sessions
+
+use std::{
+    fmt::Write,
+    fs,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// how many visits to remember before forgetting the oldest
+const MAX_VISITS: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Visit {
+    pub url: String,
+    /// the page's title, or empty if it didn't have one
+    pub title: String,
+    pub time: SystemTime,
+}
+
+/// every page visited, oldest first, optionally saved to a file
+#[derive(Default)]
+pub struct History {
+    path: Option<PathBuf>,
+    visits: Vec<Visit>,
+}
+
+impl History {
+    /// read history from a file, or keep it in memory if there isn't one
+    pub fn open(path: Option<PathBuf>) -> Self {
+        let visits = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|file| file.lines().filter_map(parse_line).collect())
+            .unwrap_or_default();
+        Self { path, visits }
+    }
+
+    /// pure fn to get every visit, oldest first
+    pub fn visits(&self) -> &[Visit] {
+        &self.visits
+    }
+
+    /// remember a visit to a page; visiting the page that was visited last, like when reloading it, just updates that visit
+    pub fn record(&mut self, url: &str, title: &str) {
+        let visit = Visit {
+            url: String::from(url),
+            title: title.split_whitespace().collect::<Vec<_>>().join(" "),
+            time: SystemTime::now(),
+        };
+        if self.visits.last().is_some_and(|last| last.url == url) {
+            self.visits.pop();
+        }
+        self.visits.push(visit);
+        if self.visits.len() > MAX_VISITS {
+            self.visits.drain(..self.visits.len() - MAX_VISITS);
+        }
+        self.save();
+    }
+
+    /// write every visit to the file
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let mut file = String::new();
+        for visit in &self.visits {
+            let time = visit
+                .time
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let _ = writeln!(file, "{time}\t{}\t{}", visit.url, visit.title);
+        }
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = fs::write(path, file);
+    }
+}
+
+/// pure fn to read a visit from a line of the history file
+fn parse_line(line: &str) -> Option<Visit> {
+    let mut fields = line.splitn(3, '\t');
+    let time = fields.next()?.parse().ok()?;
+    Some(Visit {
+        url: String::from(fields.next()?),
+        title: String::from(fields.next().unwrap_or_default()),
+        time: UNIX_EPOCH + Duration::from_secs(time),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::History;
+
+    #[test]
+    fn visits() {
+        let path = std::env::temp_dir().join(format!("nasir-history-{}", std::process::id()));
+        let mut history = History::open(Some(path.clone()));
+        history.record("https://example.com/", "Example\tDomain");
+        history.record("https://example.org/", "");
+        // reloading doesn't count as another visit
+        history.record("https://example.org/", "Loaded");
+        let history = History::open(Some(path.clone()));
+        let visits: Vec<(&str, &str)> = history
+            .visits()
+            .iter()
+            .map(|visit| (visit.url.as_str(), visit.title.as_str()))
+            .collect();
+        assert_eq!(
+            visits,
+            [
+                ("https://example.com/", "Example Domain"),
+                ("https://example.org/", "Loaded")
+            ]
+        );
+        fs::remove_file(path).unwrap();
+    }
+}
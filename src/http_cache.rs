@@ -0,0 +1,280 @@
+//! Deciding when a stored response can be reused, following RFC 9111
+
+use std::time::{Duration, SystemTime};
+
+use reqwest::header::{
+    HeaderMap, HeaderValue, AGE, CACHE_CONTROL, CONTENT_TYPE, DATE, ETAG, EXPIRES,
+    IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, PRAGMA, VARY,
+};
+use serde::{Deserialize, Serialize};
+
+/// responses without any caching headers are never guessed to be fresh for longer than this
+const MAX_HEURISTIC_FRESHNESS: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// how much to trust the cache when loading something
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheMode {
+    /// use whatever is stored, like when going back or redrawing a page
+    Reuse,
+    /// use stored responses that are still fresh, and revalidate stale ones
+    Normal,
+    /// revalidate stored responses even if they're fresh
+    Revalidate,
+    /// ignore stored responses and tell any caches along the way to do the same
+    Bypass,
+}
+
+/// what a response's headers say about reusing it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CachePolicy {
+    /// when the response arrived
+    received: SystemTime,
+    /// how long after arriving the response can be used without asking the server
+    freshness: Duration,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// lowercase names of the request headers the response depends on
+    pub vary: Vec<String>,
+    /// the response mustn't be saved anywhere that outlasts the session
+    pub no_store: bool,
+    /// the status code the response came with
+    pub status: u16,
+    /// lowercase mime type of the response, without parameters like `charset`
+    #[serde(default)]
+    pub content_type: Option<String>,
+}
+
+impl CachePolicy {
+    /// pure fn to read the caching headers of a response
+    pub fn new(headers: &HeaderMap, received: SystemTime) -> Self {
+        let directives = cache_control(headers);
+        let directive = |name: &str| {
+            directives
+                .iter()
+                .find(|(directive, _)| directive == name)
+                .map(|(_, value)| value.as_deref())
+        };
+        let date = header_date(headers.get(DATE)).unwrap_or(received);
+        let no_store = directive("no-store").is_some();
+        let freshness = if no_store || directive("no-cache").is_some() {
+            Duration::ZERO
+        } else if let Some(max_age) = directive("max-age") {
+            // a max-age that doesn't make sense means the response is already stale
+            Duration::from_secs(max_age.and_then(|age| age.parse().ok()).unwrap_or(0))
+        } else if let Some(expires) = headers.get(EXPIRES) {
+            header_date(Some(expires))
+                .and_then(|expires| expires.duration_since(date).ok())
+                .unwrap_or(Duration::ZERO)
+        } else if let Some(last_modified) = header_date(headers.get(LAST_MODIFIED)) {
+            // a page that hasn't changed in a while probably won't change soon
+            (date.duration_since(last_modified).unwrap_or(Duration::ZERO) / 10)
+                .min(MAX_HEURISTIC_FRESHNESS)
+        } else {
+            Duration::ZERO
+        };
+        // time the response already spent in other caches counts against it
+        let age = headers
+            .get(AGE)
+            .and_then(|age| age.to_str().ok()?.trim().parse().ok())
+            .map_or(Duration::ZERO, Duration::from_secs);
+        Self {
+            received,
+            freshness: freshness.saturating_sub(age),
+            etag: header_string(headers.get(ETAG)),
+            last_modified: header_string(headers.get(LAST_MODIFIED)),
+            vary: headers
+                .get_all(VARY)
+                .iter()
+                .filter_map(|vary| vary.to_str().ok())
+                .flat_map(|vary| vary.split(','))
+                .map(|name| name.trim().to_lowercase())
+                .filter(|name| !name.is_empty())
+                .collect(),
+            no_store,
+            status: 200,
+            content_type: header_string(headers.get(CONTENT_TYPE))
+                .map(|content_type| mime_type(&content_type)),
+        }
+    }
+
+    /// pure fn to update the policy after the server says the stored response is still good
+    pub fn revalidated(&self, headers: &HeaderMap, received: SystemTime) -> Self {
+        let new = Self::new(headers, received);
+        Self {
+            etag: new.etag.or_else(|| self.etag.clone()),
+            last_modified: new.last_modified.or_else(|| self.last_modified.clone()),
+            vary: if new.vary.is_empty() {
+                self.vary.clone()
+            } else {
+                new.vary
+            },
+            // the status of a revalidation is always 304, which isn't the status of the stored response
+            status: self.status,
+            content_type: new.content_type.or_else(|| self.content_type.clone()),
+            ..new
+        }
+    }
+
+    /// pure fn to check if the response can still be used without asking the server
+    pub fn is_fresh(&self, now: SystemTime) -> bool {
+        // `Vary: *` means no request is ever the same as the one the response was for
+        !self.vary.iter().any(|name| name == "*")
+            && now
+                .duration_since(self.received)
+                .is_ok_and(|age| age < self.freshness)
+    }
+
+    /// pure fn to check if the response was a success, not an error
+    pub const fn is_success(&self) -> bool {
+        self.status >= 200 && self.status < 300
+    }
+
+    /// pure fn to get the headers that ask the server whether the stored response changed
+    pub fn conditional_headers(&self) -> HeaderMap {
+        [
+            (IF_NONE_MATCH, &self.etag),
+            (IF_MODIFIED_SINCE, &self.last_modified),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, HeaderValue::from_str(value.as_ref()?).ok()?)))
+        .collect()
+    }
+}
+
+/// pure fn to get the headers that ask every cache along the way to skip its stored copy
+pub fn bypass_headers() -> HeaderMap {
+    HeaderMap::from_iter([
+        (CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+        (PRAGMA, HeaderValue::from_static("no-cache")),
+    ])
+}
+
+/// pure fn to split `Cache-Control` into lowercase directives and their values
+fn cache_control(headers: &HeaderMap) -> Vec<(String, Option<String>)> {
+    let mut directives: Vec<(String, Option<String>)> = headers
+        .get_all(CACHE_CONTROL)
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .flat_map(|header| header.split(','))
+        .map(|directive| match directive.split_once('=') {
+            Some((name, value)) => (
+                name.trim().to_lowercase(),
+                Some(String::from(value.trim().trim_matches('"'))),
+            ),
+            None => (directive.trim().to_lowercase(), None),
+        })
+        .collect();
+    // old servers still say `Pragma: no-cache`
+    if directives.is_empty()
+        && headers
+            .get(PRAGMA)
+            .and_then(|pragma| pragma.to_str().ok())
+            .is_some_and(|pragma| pragma.eq_ignore_ascii_case("no-cache"))
+    {
+        directives.push((String::from("no-cache"), None));
+    }
+    directives
+}
+
+/// pure fn to get the lowercase mime type out of a `Content-Type`, like `text/html` from `text/html; charset=utf-8`
+pub fn mime_type(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase()
+}
+
+/// pure fn to read a header that holds an HTTP date
+fn header_date(header: Option<&HeaderValue>) -> Option<SystemTime> {
+    httpdate::parse_http_date(header?.to_str().ok()?).ok()
+}
+
+/// pure fn to read a header that should be plain text
+fn header_string(header: Option<&HeaderValue>) -> Option<String> {
+    header?.to_str().ok().map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use reqwest::header::{HeaderMap, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH};
+
+    use super::CachePolicy;
+
+    fn headers(headers: &[(&'static str, &'static str)]) -> HeaderMap {
+        headers
+            .iter()
+            .map(|&(name, value)| (name.parse().unwrap(), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    #[test]
+    fn freshness() {
+        let received = httpdate::parse_http_date("Sun, 18 Oct 2026 12:00:00 GMT").unwrap();
+        let later = |secs| received + Duration::from_secs(secs);
+        let policy = CachePolicy::new(
+            &headers(&[("cache-control", "public, max-age=60")]),
+            received,
+        );
+        assert!(policy.is_fresh(later(59)));
+        assert!(!policy.is_fresh(later(60)));
+        let policy = CachePolicy::new(
+            &headers(&[("cache-control", "max-age=60"), ("age", "50")]),
+            received,
+        );
+        assert!(!policy.is_fresh(later(10)));
+        let policy = CachePolicy::new(
+            &headers(&[
+                ("date", "Sun, 18 Oct 2026 12:00:00 GMT"),
+                ("expires", "Sun, 18 Oct 2026 13:00:00 GMT"),
+            ]),
+            received,
+        );
+        assert!(policy.is_fresh(later(3599)));
+        assert!(!policy.is_fresh(later(3600)));
+        let policy = CachePolicy::new(&headers(&[("expires", "0")]), received);
+        assert!(!policy.is_fresh(received));
+        let policy = CachePolicy::new(
+            &headers(&[("cache-control", "no-cache, max-age=60")]),
+            received,
+        );
+        assert!(!policy.is_fresh(received));
+        let policy = CachePolicy::new(
+            &headers(&[("cache-control", "max-age=60"), ("vary", "*")]),
+            received,
+        );
+        assert!(!policy.is_fresh(received));
+        assert!(!CachePolicy::new(&HeaderMap::new(), SystemTime::now()).is_fresh(SystemTime::now()));
+    }
+
+    #[test]
+    fn revalidation() {
+        let received = SystemTime::now();
+        let policy = CachePolicy::new(
+            &headers(&[
+                ("etag", "\"abc\""),
+                ("last-modified", "Sun, 18 Oct 2026 12:00:00 GMT"),
+                ("vary", "Accept-Language, Cookie"),
+                ("content-type", "Text/HTML; charset=utf-8"),
+            ]),
+            received,
+        );
+        assert_eq!(policy.vary, ["accept-language", "cookie"]);
+        assert_eq!(policy.content_type.as_deref(), Some("text/html"));
+        let conditional = policy.conditional_headers();
+        assert_eq!(conditional[IF_NONE_MATCH], "\"abc\"");
+        assert_eq!(
+            conditional[IF_MODIFIED_SINCE],
+            "Sun, 18 Oct 2026 12:00:00 GMT"
+        );
+        let policy = policy.revalidated(&headers(&[("cache-control", "max-age=60")]), received);
+        assert!(policy.is_fresh(received));
+        assert_eq!(policy.conditional_headers()[IF_NONE_MATCH], "\"abc\"");
+        assert_eq!(policy.vary, ["accept-language", "cookie"]);
+        assert_eq!(policy.content_type.as_deref(), Some("text/html"));
+    }
+}
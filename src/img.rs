@@ -1,3 +1,4 @@
+use std::fmt::Write;
 use std::io::Cursor;
 
 use image::io::Reader as ImageReader;
@@ -58,11 +59,12 @@ pub fn approximate_image(
                 b += px[2] as usize * px[3] as usize / 256;
             }
             let (r, g, b) = (r / pix_len, g / pix_len, b / pix_len);
-            current_line.push_str(&format!(
+            let _ = write!(
+                current_line,
                 "\x1b[38;5;{};38;2;{r};{g};{b}m█\x1b[0m",
                 // "\x1b[38;5;{}m█\x1b[0m",
                 rgb_to_256((r, g, b))
-            ));
+            );
         }
         termlines.push(TerminalLine::from(current_line));
     }
@@ -73,16 +75,16 @@ pub fn approximate_image(
 const fn get_img_viewport((img_w, img_h): (u32, u32), (term_w, term_h): (u32, u32)) -> (u32, u32) {
     // start with the image size
     // if the image is too tall, squash it
-    let (img_w_2, img_h_2) = if img_h > term_h {
+    let (squashed_w, squashed_h) = if img_h > term_h {
         (img_w * term_h / img_h, term_h)
     } else {
         (img_w, img_h)
     };
     // if the image is too long, squish it
-    if img_w_2 > term_w {
-        (term_w, img_h_2 * term_w / img_w_2)
+    if squashed_w > term_w {
+        (term_w, squashed_h * term_w / squashed_w)
     } else {
-        (img_w_2, img_h_2)
+        (squashed_w, squashed_h)
     }
 }
 
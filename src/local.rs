@@ -0,0 +1,138 @@
+//! Pages read from the local filesystem instead of the network
+
+use std::{
+    fmt::Write,
+    fs::{self, Metadata},
+    io,
+    path::{Path, PathBuf},
+};
+
+use url::Url;
+
+use crate::utils::format_bytes;
+
+/// get the `file://` url of a path typed without a scheme, if there's something there
+pub fn path_url(input: &str) -> Option<String> {
+    let input = input.trim();
+    let path = match input.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir()?.join(rest),
+        None => PathBuf::from(input),
+    };
+    // `example.com` could be a file in the current directory, but it's much more likely to be a site
+    if !(path.is_absolute() || input.starts_with('.') || input.starts_with('~')) {
+        return None;
+    }
+    let path = path.canonicalize().ok()?;
+    let mut url = Url::from_file_path(&path).ok()?;
+    if path.is_dir() && !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    Some(String::from(url))
+}
+
+/// read a file, or list a directory as a page
+pub fn read(url: &str) -> Result<Vec<u8>, String> {
+    let url = Url::parse(url).map_err(|err| format!("{err} ({url})"))?;
+    let path = url
+        .to_file_path()
+        .map_err(|()| format!("Not a local path: {url}"))?;
+    let read = if path.is_dir() {
+        listing(&path, &url).map(String::into_bytes)
+    } else {
+        fs::read(&path)
+    };
+    read.map_err(|err| format!("Couldn't read {}: {err}", path.display()))
+}
+
+/// pure fn to get the mime type of a local file that isn't a page Nasir can sniff, like gemtext
+pub fn content_type(url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let extension = path.rsplit_once('.')?.1.to_lowercase();
+    match extension.as_str() {
+        "gmi" | "gemini" => Some(String::from("text/gemini")),
+        _ => None,
+    }
+}
+
+/// list a directory as html, with folders first
+fn listing(dir: &Path, url: &Url) -> io::Result<String> {
+    let mut entries: Vec<(String, Metadata)> = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            Some((
+                entry.file_name().to_string_lossy().into_owned(),
+                entry.metadata().ok()?,
+            ))
+        })
+        .collect();
+    entries
+        .sort_by(|(a, a_meta), (b, b_meta)| b_meta.is_dir().cmp(&a_meta.is_dir()).then(a.cmp(b)));
+    let title = escape_html(&dir.display().to_string());
+    let mut html =
+        format!("<html><head><title>{title}</title></head><body><h1>Index of {title}</h1>");
+    if let Some(parent) = dir
+        .parent()
+        .and_then(|parent| Url::from_directory_path(parent).ok())
+    {
+        let _ = write!(html, "<p><a href=\"{parent}\">../</a></p>");
+    }
+    for (name, meta) in entries {
+        let mut href = url.clone();
+        // joining a name onto a directory url that doesn't end in `/` would replace the directory
+        href.set_path(&format!("{}/{name}", url.path().trim_end_matches('/')));
+        let (name, size) = if meta.is_dir() {
+            (format!("{name}/"), String::from("-"))
+        } else {
+            (
+                name,
+                format_bytes(usize::try_from(meta.len()).unwrap_or(usize::MAX)),
+            )
+        };
+        let modified = meta
+            .modified()
+            .map_or_else(|_| String::from("-"), httpdate::fmt_http_date);
+        let _ = write!(
+            html,
+            "<p><a href=\"{}\">{}    {size}    {modified}</a></p>",
+            escape_html(href.as_str()),
+            escape_html(&name)
+        );
+    }
+    html.push_str("</body></html>");
+    Ok(html)
+}
+
+/// pure fn to make text safe to put in html
+fn escape_html(txt: &str) -> String {
+    txt.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{path_url, read};
+
+    #[test]
+    fn directories() {
+        let dir = std::env::temp_dir().join(format!("nasir-local-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub folder")).unwrap();
+        fs::write(dir.join("a<b>.html"), "<p>hi</p>").unwrap();
+        let url = path_url(dir.to_str().unwrap()).unwrap();
+        assert!(url.starts_with("file:///") && url.ends_with('/'));
+        assert_eq!(path_url("example.com"), None);
+        let listing = String::from_utf8(read(&url).unwrap()).unwrap();
+        // folders come first, and names can't break the page
+        let folder = listing.find("sub%20folder\">sub folder/").unwrap();
+        let file = listing
+            .find("a%3Cb%3E.html\">a&lt;b&gt;.html    9 B")
+            .unwrap();
+        assert!(folder < file);
+        assert_eq!(read(&format!("{url}a%3Cb%3E.html")).unwrap(), b"<p>hi</p>");
+        assert!(read(&format!("{url}missing")).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -2,30 +2,87 @@
 
 use core::time;
 use std::{
+    collections::BTreeMap,
     io::{stdin, stdout, Write},
+    ops::Range,
+    path::PathBuf,
     sync::{Arc, Mutex},
+    time::{Duration, UNIX_EPOCH},
 };
 
-use cacher::{get_from_cache_blocking, ByteCacher, Cacher};
+use cacher::{
+    resume_download, save_for_offline, start_download, start_load, start_post, ByteCacher, Cacher,
+    FetchError, Fetched, Loading,
+};
 use clap::Parser;
 use crossterm::{
-    event::{self, KeyCode},
+    event::{self, KeyCode, KeyModifiers},
     execute,
     terminal::{self, disable_raw_mode, enable_raw_mode, SetTitle},
 };
 use lazy_regex::lazy_regex;
-use utils::transform_url_text;
+use utils::{fit_end, format_bytes, normalize_input, transform_url_text, wrap};
 
+mod about;
+mod bookmarks;
 mod cacher;
+mod config;
+mod cookies;
+mod data_url;
+mod disk_cache;
+mod download;
+mod encoding;
+mod gemini;
+mod gopher;
+mod history;
+mod http_cache;
 mod img;
+mod local;
 mod parser;
+mod proxy;
+mod source;
+mod tls;
 mod types;
 mod utils;
 
-use crate::{parser::parse_html, utils::get_link_destination};
+use crate::{
+    config::{parse_header, parse_list, Config},
+    cookies::CookieJar,
+    disk_cache::DiskCache,
+    http_cache::CacheMode,
+    parser::parse_html,
+    tls::CertificateInfo,
+    utils::{get_link_destination, may_follow},
+};
 use img::{approximate_image, get_image};
 use types::prelude::*;
 
+/// what's shown when a page from the network points at a local file
+const LOCAL_FILE_BLOCKED: &str = "Pages from the network can't open local files";
+
+/// every key the browser listens for while browsing, with what it does, for `about:keys`
+const KEYS: &[(&str, &str)] = &[
+    ("up, k", "move the cursor up"),
+    ("down, j", "move the cursor down"),
+    ("page up", "move the cursor up 10 lines"),
+    ("page down", "move the cursor down 10 lines"),
+    ("enter", "follow a link or use a form control"),
+    (
+        "esc",
+        "go back, or quit on the first page; stop a page loading",
+    ),
+    (":", "go to an address or search"),
+    ("r", "reload the page"),
+    ("R", "reload the page and its images, ignoring the cache"),
+    ("p", "proceed with a certificate that can't be trusted"),
+    ("o", "switch offline mode on or off"),
+    ("s", "save the page and its images for offline"),
+    ("d", "download the link under the cursor, or the page"),
+    ("u", "view the page's source, or the page from its source"),
+    ("b", "bookmark the page, or forget its bookmark"),
+    ("y", "copy the current line"),
+];
+
 #[derive(Parser)]
 struct Args {
     /// the page to visit
@@ -33,20 +90,127 @@ struct Args {
     /// print extra debug information
     #[clap(short, long)]
     verbose: bool,
+    /// list saved cookies for a site, or for every site, then exit
+    #[clap(long, value_name = "SITE", num_args = 0..=1, default_missing_value = "")]
+    cookies: Option<String>,
+    /// forget saved cookies for a site, or for every site, then exit
+    #[clap(long, value_name = "SITE", num_args = 0..=1, default_missing_value = "")]
+    clear_cookies: Option<String>,
+    /// delete every response saved in the disk cache, then exit
+    #[clap(long)]
+    clear_cache: bool,
+    /// read settings from this file instead of the default config file
+    #[clap(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// the User-Agent header to send
+    #[clap(long)]
+    user_agent: Option<String>,
+    /// an extra `Name: value` header to send with every request
+    #[clap(short = 'H', long = "header", value_name = "HEADER", value_parser = parse_header)]
+    headers: Vec<(String, String)>,
+    /// seconds to wait for a request before giving up
+    #[clap(long, value_name = "SECONDS")]
+    timeout: Option<u64>,
+    /// only show pages that are already cached, without touching the network
+    #[clap(long)]
+    offline: bool,
+    /// send every request through this proxy, like `http://proxy:8080` or `socks5h://localhost:9050`
+    #[clap(long, value_name = "URL")]
+    proxy: Option<String>,
+    /// comma-separated hosts to reach without the proxy
+    #[clap(long, value_name = "HOSTS")]
+    no_proxy: Option<String>,
+    /// the `user:password` to log into the proxy with
+    #[clap(long, value_name = "USER:PASSWORD")]
+    proxy_user: Option<String>,
+}
+
+impl Args {
+    /// load the config file, fill in what it doesn't say from the environment, and apply the command line flags
+    /// on top of both
+    fn config(&self) -> Result<Config, String> {
+        let mut config = Config::load(self.config.clone())?;
+        config.proxy_from_env(|name| std::env::var(name).ok());
+        self.apply(&mut config);
+        Ok(config)
+    }
+
+    /// apply the command line flags, which win over everything else
+    fn apply(&self, config: &mut Config) {
+        if let Some(user_agent) = &self.user_agent {
+            config.user_agent.clone_from(user_agent);
+        }
+        for (name, value) in &self.headers {
+            config.set_header(name, value);
+        }
+        if let Some(timeout) = self.timeout {
+            config.timeout = timeout;
+        }
+        config.offline |= self.offline;
+        if let Some(proxy) = &self.proxy {
+            // a proxy on the command line replaces every other one
+            config.proxy = Some(proxy.clone());
+            config.http_proxy = None;
+            config.https_proxy = None;
+        }
+        if let Some(hosts) = &self.no_proxy {
+            config.no_proxy = parse_list(hosts);
+        }
+        if let Some(user) = &self.proxy_user {
+            let (username, password) = user.split_once(':').unwrap_or((user, ""));
+            config.proxy_username = Some(String::from(username));
+            config.proxy_password = Some(String::from(password));
+        }
+    }
 }
 
 fn main() {
     let args = Args::parse();
-    browse(
-        &args.url.unwrap_or_else(|| {
-            print!("Enter URL\r\n:");
-            stdout().flush().unwrap();
-            let mut url = String::new();
-            stdin().read_line(&mut url).unwrap();
-            url
-        }),
-        args.verbose,
-    );
+    if let Some(site) = args.cookies {
+        let site = Some(site.as_str()).filter(|site| !site.is_empty());
+        for cookie in CookieJar::load().list(site) {
+            let expires = cookie.expires.map_or_else(
+                || String::from("session"),
+                |expires| {
+                    httpdate::fmt_http_date(
+                        UNIX_EPOCH + Duration::from_secs(expires.try_into().unwrap_or(0)),
+                    )
+                },
+            );
+            println!(
+                "{}{}\t{}={}\t{expires}",
+                cookie.domain, cookie.path, cookie.name, cookie.value
+            );
+        }
+        return;
+    }
+    if let Some(site) = args.clear_cookies {
+        CookieJar::load().clear(Some(site.as_str()).filter(|site| !site.is_empty()));
+        return;
+    }
+    if args.clear_cache {
+        if let Err(err) = DiskCache::default_dir().map_or(Ok(()), |dir| DiskCache::clear(&dir)) {
+            eprintln!("{err}");
+        }
+        return;
+    }
+    let cacher = match args.config().and_then(|config| Cacher::new(&config)) {
+        Ok(cacher) => cacher,
+        Err(err) => {
+            eprintln!("{err}");
+            return;
+        }
+    };
+    let url = args.url.unwrap_or_else(|| {
+        print!("Enter URL\r\n:");
+        stdout().flush().unwrap();
+        let mut url = String::new();
+        stdin().read_line(&mut url).unwrap();
+        url
+    });
+    let url =
+        local::path_url(&url).unwrap_or_else(|| normalize_input(&url, &cacher.config().search_url));
+    browse(&url, cacher, args.verbose);
 }
 
 // fn fetch(url: &str) -> Result<String, reqwest::Error> {
@@ -54,19 +218,50 @@ fn main() {
 //     Ok(body)
 // }
 
+/// display a fetched page; returns where the page ended up after any redirects
 fn fetch_html(
     url: &str,
+    fetched: Result<Fetched, FetchError>,
     set_title: &mut SetTitle<RStr>,
     cacher: &Arc<Mutex<ByteCacher>>,
+    forms: &mut BTreeMap<String, FormState>,
     verbose: bool,
-) -> Vec<TerminalLine> {
-    let bytes = match get_from_cache_blocking(cacher, url) {
+) -> (RStr, Vec<TerminalLine>) {
+    let Fetched {
+        url,
+        content_type,
+        bytes,
+    } = match fetched {
         Ok(response) => response,
-        Err(err) => return vec![TerminalLine::from(format!("Network Error: {err}"))],
+        Err(err) => return (url.into(), error_page(&err)),
     };
+    match content_type.as_deref() {
+        Some("text/gemini") => {
+            let forms = forms.entry(url.clone()).or_default();
+            forms.clear_layout();
+            let lines = gemini::render(
+                &String::from_utf8_lossy(&bytes),
+                &url,
+                set_title,
+                cacher,
+                forms,
+                verbose,
+            );
+            return (url.into(), lines);
+        }
+        Some(gopher::MENU) => {
+            let lines = gopher::render_menu(&String::from_utf8_lossy(&bytes));
+            return (url.into(), lines);
+        }
+        Some("text/plain") => {
+            let lines = text_page(&String::from_utf8_lossy(&bytes));
+            return (url.into(), lines);
+        }
+        _ => {}
+    }
     // if we can get an image, return it
     if let Ok(img) = get_image(&bytes) {
-        return approximate_image(
+        let lines = approximate_image(
             &img,
             {
                 let size = terminal::size().unwrap();
@@ -74,47 +269,258 @@ fn fetch_html(
             },
             verbose,
         );
+        return (url.into(), lines);
+    }
+    if content_type.as_deref() == Some("application/octet-stream") {
+        let lines = download_page(&url, bytes.len());
+        return (url.into(), lines);
     }
     let body: String = match String::from_utf8(bytes) {
         Ok(body) => body,
-        Err(err) => return vec![TerminalLine::from(format!("Network Error: {err}"))],
+        Err(err) => {
+            return (
+                url.into(),
+                vec![TerminalLine::from(format!("Network Error: {err}"))],
+            )
+        }
     };
     if verbose {
         print!("response body: {body}\r\n");
     }
-    match parse_html(&body) {
+    let lines = match parse_html(&body) {
         Ok(html) => {
             if verbose {
                 print!("Parsed HTML: {html:#?}");
             }
-            html.display(set_title, cacher, url, verbose)
+            let forms = forms.entry(url.clone()).or_default();
+            forms.clear_layout();
+            let lines = html.display(set_title, cacher, &url, TextDirection::Auto, forms, verbose);
+            forms.set_document(html);
+            lines
         }
         Err(err) => vec![TerminalLine::from(format!("HTML Parsing Error: {err}"))],
+    };
+    (url.into(), lines)
+}
+
+/// show plain text as it is, only wrapping lines too long for the screen
+fn text_page(body: &str) -> Vec<TerminalLine> {
+    let width = (terminal::size().unwrap().0 - 1) as usize;
+    body.lines()
+        .map(|line| line.replace('\t', "    "))
+        .flat_map(|line| {
+            if utils::display_width(&line) <= width {
+                vec![line]
+            } else {
+                wrap(&line, width)
+            }
+        })
+        .map(TerminalLine::from)
+        .collect()
+}
+
+/// offer to save a file Nasir can't show
+fn download_page(url: &str, size: usize) -> Vec<TerminalLine> {
+    let width = (terminal::size().unwrap().0 - 1) as usize;
+    let mut page = vec![
+        TerminalLine::from("\x1b[30;47mDownload\x1b[0m"),
+        TerminalLine::from(""),
+    ];
+    let explanation = format!(
+        "{} ({}) isn't something Nasir can show. Press `d` to save it to {}.",
+        download::file_name(url),
+        format_bytes(size),
+        download::default_dir().display()
+    );
+    page.extend(
+        wrap(&explanation, width)
+            .into_iter()
+            .map(TerminalLine::from),
+    );
+    page
+}
+
+/// explain why a page couldn't be loaded
+fn error_page(err: &FetchError) -> Vec<TerminalLine> {
+    let (title, explanation, links) = match err {
+        FetchError::Network(_)
+        | FetchError::Status(_)
+        | FetchError::File(_)
+        | FetchError::Data(_)
+        | FetchError::Input { .. }
+        | FetchError::Download { .. } => {
+            return vec![TerminalLine::from(err.to_string())]
+        }
+        FetchError::RedirectLoop(chain) => (
+            "Redirect Loop",
+            "These pages keep redirecting to each other, so this one will never load:",
+            chain.clone(),
+        ),
+        FetchError::TooManyRedirects(chain) => (
+            "Too Many Redirects",
+            "This page redirected more times than the `max-redirects` setting allows:",
+            chain.clone(),
+        ),
+        FetchError::InsecureRedirect { from, to } => (
+            "Insecure Redirect",
+            "This secure page is trying to send you to a page that isn't secure. Select the link to go there anyway:",
+            vec![from.clone(), to.clone()],
+        ),
+        FetchError::Offline(url) => (
+            "Not Available Offline",
+            "This page hasn't been saved, so it can't be shown while Nasir is offline. Press `o` to go back online, then select the link to load it:",
+            vec![url.clone()],
+        ),
+        FetchError::Certificate { url, reason, info } => {
+            return certificate_page(url, reason, info.as_ref())
+        }
+        FetchError::Gemini { url, status, meta } => return gemini_page(url, *status, meta),
+    };
+    let mut page = vec![
+        TerminalLine::from(format!("\x1b[30;47m{title}\x1b[0m")),
+        TerminalLine::from(""),
+    ];
+    page.extend(
+        wrap(explanation, (terminal::size().unwrap().0 - 1) as usize)
+            .into_iter()
+            .map(TerminalLine::from),
+    );
+    page.extend(links.into_iter().map(|link| {
+        let link = RStr::from(link);
+        TerminalLine::from(link.clone()).into_link(link)
+    }));
+    page
+}
+
+/// explain why a site's certificate can't be trusted, with enough about it to decide whether to trust it anyway
+fn certificate_page(url: &str, reason: &str, info: Option<&CertificateInfo>) -> Vec<TerminalLine> {
+    let width = (terminal::size().unwrap().0 - 1) as usize;
+    let unknown = String::from("unknown");
+    let mut page = vec![
+        TerminalLine::from("\x1b[30;47mCertificate Error\x1b[0m"),
+        TerminalLine::from(""),
+    ];
+    // trusting it anyway only trusts this certificate, so there has to be one to trust
+    let proceed = info.map(|_| String::from("If you trust it anyway, press `p` to use this certificate for the rest of the session, or add the host and its fingerprint to `certificate-exceptions` in the config file."));
+    for paragraph in [
+        format!("The site's certificate can't be trusted, so it might not really be the site it says it is: {reason}"),
+        format!("Issuer: {}", info.map_or(&unknown, |info| &info.issuer)),
+        format!("SHA-256 fingerprint: {}", info.map_or(&unknown, |info| &info.fingerprint)),
+    ]
+    .into_iter()
+    .chain(proceed)
+    {
+        page.extend(wrap(&paragraph, width).into_iter().map(TerminalLine::from));
+        page.push(TerminalLine::from(""));
     }
+    let url = RStr::from(url);
+    page.push(TerminalLine::from(url.clone()).into_link(url));
+    page
+}
+
+/// explain a Gemini capsule's answer that isn't a page, along with whatever the capsule said about it
+fn gemini_page(url: &str, status: u8, meta: &str) -> Vec<TerminalLine> {
+    let width = (terminal::size().unwrap().0 - 1) as usize;
+    let title = match status {
+        30..=39 => "Redirect",
+        41 => "Server Unavailable",
+        42 => "CGI Error",
+        43 => "Proxy Error",
+        44 => "Slow Down",
+        51 => "Not Found",
+        52 => "Gone",
+        53 => "Proxy Request Refused",
+        59 => "Bad Request",
+        60 => "Certificate Required",
+        61 => "Certificate Not Authorised",
+        62 => "Certificate Not Valid",
+        40..=49 => "Temporary Failure",
+        _ => "Permanent Failure",
+    };
+    let explanation = match status {
+        30..=39 => "This capsule is sending you somewhere that isn't on Gemini. Select the link to go there:",
+        40..=49 => "The capsule couldn't answer this time, but trying again later might work:",
+        60 => "This capsule wants a client certificate. Add one for this url under `gemini-identities` in the config file, then select the link to try again:",
+        61 | 62 => "The capsule didn't accept the certificate set for this url under `gemini-identities` in the config file:",
+        _ => "The capsule won't answer this request:",
+    };
+    let mut page = vec![
+        TerminalLine::from(format!("\x1b[30;47m{title}\x1b[0m")),
+        TerminalLine::from(""),
+    ];
+    page.extend(wrap(explanation, width).into_iter().map(TerminalLine::from));
+    // a redirect's meta is where it goes, and anything else's is the capsule's own explanation
+    let link = if status / 10 == 3 {
+        RStr::from(meta)
+    } else {
+        if !meta.is_empty() {
+            page.extend(
+                wrap(&format!("{status} {meta}"), width)
+                    .into_iter()
+                    .map(|line| {
+                        TerminalLine::from(line).map(|rstr| format!("\x1b[1m{rstr}\x1b[0m").into())
+                    }),
+            );
+        }
+        RStr::from(url)
+    };
+    page.push(TerminalLine::from(link.clone()).into_link(link));
+    page
 }
 
-fn browse(url: &str, verbose: bool) {
+fn browse(url: &str, cacher: ByteCacher, verbose: bool) {
     let terminal_handler = TermHandler::new();
-    let cacher: Arc<Mutex<ByteCacher>> = Arc::new(Mutex::new(Cacher::new()));
-    let mut breadcrumbs = vec![String::from(url)];
+    let cacher = Arc::new(Mutex::new(cacher));
+    let updates = cacher.lock().unwrap().subscribe();
     let mut htmelements = Vec::new();
-    load_link(url.into(), &mut htmelements, &cacher, verbose);
+    let mut forms = BTreeMap::new();
+    let Some(url) = load_link(
+        url.into(),
+        CacheMode::Normal,
+        &mut htmelements,
+        &cacher,
+        &mut forms,
+        verbose,
+    ) else {
+        return;
+    };
+    let mut breadcrumbs = vec![String::from(&*url)];
     if verbose {
         print!("{htmelements:#?}\r\n");
     }
     let mut focused = 0;
     'browsing: loop {
         if htmelements.len() <= focused {
-            focused = htmelements.len() - 1;
+            focused = htmelements.len().saturating_sub(1);
         }
         let lines = render_lines(&htmelements, focused, verbose);
+        // images on screen get fetched before the rest of the page's
+        cacher.lock().unwrap().set_visible(
+            htmelements[window(htmelements.len(), focused, verbose)]
+                .iter()
+                .filter_map(|line| match line.interaction() {
+                    InteractionType::Image(src) => Some(src.clone()),
+                    _ => None,
+                }),
+        );
         // clear the screen
         print!("\x1B[2J\x1B[1;1H");
         // print out the current window
         for l in lines {
             print!("{l}\r\n");
         }
-        while matches!(event::poll(time::Duration::from_secs(0)), Ok(false)) {}
+        while matches!(event::poll(time::Duration::from_millis(50)), Ok(false)) {
+            // images and such arrived for this page => draw them where the placeholders were
+            if updates.try_iter().count() > 0 {
+                if let Some(lines) =
+                    redraw(breadcrumbs.last().unwrap(), &cacher, &mut forms, verbose)
+                {
+                    let old = std::mem::replace(&mut htmelements, lines);
+                    focused = refocus(&old, &htmelements, focused);
+                }
+                continue 'browsing;
+            }
+        }
         while matches!(event::poll(time::Duration::from_secs(0)), Ok(true)) {
             if let Ok(event::Event::Key(event::KeyEvent {
                 code,
@@ -127,38 +533,76 @@ fn browse(url: &str, verbose: bool) {
                     &mut breadcrumbs,
                     &mut htmelements,
                     &cacher,
+                    &mut forms,
                     verbose,
                     &mut focused,
                 ) {
                     break 'browsing;
                 }
+                // only the pages that can be gone back to keep their documents and what was typed into them
+                forms.retain(|url, _| breadcrumbs.contains(url));
             }
         }
     }
+    cacher.lock().unwrap().flush();
     // make sure term handler lives till here
     drop(terminal_handler);
 }
 
 /// handle a key press event
+#[allow(clippy::too_many_lines)]
 fn browser_key_event(
     code: KeyCode,
     breadcrumbs: &mut Vec<String>,
     htmelements: &mut Vec<TerminalLine>,
     cacher: &Arc<Mutex<ByteCacher>>,
+    forms: &mut BTreeMap<String, FormState>,
     verbose: bool,
     focused: &mut usize,
 ) -> bool {
+    let current = breadcrumbs.last().unwrap();
+    if let Some(page_forms) = forms
+        .get_mut(current)
+        .filter(|page_forms| page_forms.editing().is_some())
+    {
+        let editing = page_forms.editing();
+        let submit = form_key_event(code, page_forms);
+        let submission = editing
+            .filter(|_| submit)
+            .and_then(|id| page_forms.submit(id, current));
+        if let Some(submission) = submission {
+            submit_form(
+                &submission,
+                breadcrumbs,
+                htmelements,
+                cacher,
+                forms,
+                verbose,
+            );
+            *focused = 0;
+        } else {
+            relayout(current, htmelements, cacher, page_forms, verbose);
+        }
+        return false;
+    }
     match code {
         KeyCode::Esc => {
             let current = breadcrumbs.pop().unwrap();
             if let Some(last) = breadcrumbs.last() {
-                load_link(
+                if load_link(
                     get_link_destination(&current, &RStr::from(last.as_ref())),
+                    CacheMode::Reuse,
                     htmelements,
                     cacher,
+                    forms,
                     verbose,
-                );
-                *focused = 0;
+                )
+                .is_some()
+                {
+                    *focused = 0;
+                } else {
+                    breadcrumbs.push(current);
+                }
             } else {
                 return true;
             }
@@ -167,15 +611,20 @@ fn browser_key_event(
         KeyCode::PageUp => *focused = focused.saturating_sub(10),
         KeyCode::Down | KeyCode::Char('j') => *focused += 1,
         KeyCode::PageDown => *focused = focused.saturating_add(10),
-        KeyCode::Enter => {
-            if let InteractionType::Link(link) = htmelements[*focused].interaction() {
+        KeyCode::Enter => match htmelements[*focused].interaction() {
+            InteractionType::Link(link) => {
                 let current = breadcrumbs.last().unwrap();
-                let link = load_link(
-                    get_link_destination(current, link),
-                    htmelements,
-                    cacher,
-                    verbose,
-                );
+                let link = get_link_destination(current, link);
+                if !may_follow(current, &link) {
+                    notice(htmelements, LOCAL_FILE_BLOCKED);
+                    *focused = 0;
+                    return false;
+                }
+                let Some(link) =
+                    load_link(link, CacheMode::Normal, htmelements, cacher, forms, verbose)
+                else {
+                    return false;
+                };
                 breadcrumbs.push(String::from(&*link));
                 *focused =
                     lazy_regex!("#([\\w\\d_%\\-]*)$")
@@ -188,15 +637,205 @@ fn browser_key_event(
                                 .unwrap_or(0)
                         });
             }
-        }
+            &InteractionType::FormControl(id) => {
+                let current = breadcrumbs.last().unwrap();
+                let page_forms = forms.entry(current.clone()).or_default();
+                let submission = if page_forms.activate(id) {
+                    page_forms.submit(id, current)
+                } else {
+                    None
+                };
+                if let Some(submission) = submission {
+                    submit_form(
+                        &submission,
+                        breadcrumbs,
+                        htmelements,
+                        cacher,
+                        forms,
+                        verbose,
+                    );
+                    *focused = 0;
+                } else {
+                    relayout(current, htmelements, cacher, page_forms, verbose);
+                }
+            }
+            _ => {}
+        },
         KeyCode::Char('r') => {
             load_link(
                 RStr::from(breadcrumbs.last().unwrap().as_ref()),
+                CacheMode::Revalidate,
                 htmelements,
                 cacher,
+                forms,
                 verbose,
             );
         }
+        KeyCode::Char('R') => {
+            let current = breadcrumbs.last().unwrap();
+            cacher.lock().unwrap().forget_page(current);
+            load_link(
+                RStr::from(current.as_ref()),
+                CacheMode::Bypass,
+                htmelements,
+                cacher,
+                forms,
+                verbose,
+            );
+        }
+        KeyCode::Char('p') => {
+            // the certificate might belong to a page this one redirected to, which the page links
+            let current = breadcrumbs.last().unwrap();
+            let link = match htmelements[*focused].interaction() {
+                InteractionType::Link(link) => get_link_destination(current, link),
+                _ => RStr::from(current.as_ref()),
+            };
+            let proceed = {
+                let mut cacher = cacher.lock().unwrap();
+                cacher.proceed_anyway(current) || cacher.proceed_anyway(&link)
+            };
+            if proceed {
+                load_link(
+                    RStr::from(current.as_ref()),
+                    CacheMode::Normal,
+                    htmelements,
+                    cacher,
+                    forms,
+                    verbose,
+                );
+            }
+        }
+        KeyCode::Char('o') => {
+            let offline = {
+                let mut cacher = cacher.lock().unwrap();
+                let offline = !cacher.is_offline();
+                cacher.set_offline(offline);
+                offline
+            };
+            load_link(
+                RStr::from(breadcrumbs.last().unwrap().as_ref()),
+                CacheMode::Reuse,
+                htmelements,
+                cacher,
+                forms,
+                verbose,
+            );
+            notice(
+                htmelements,
+                if offline {
+                    "Offline: only cached pages will load"
+                } else {
+                    "Online"
+                },
+            );
+        }
+        KeyCode::Char('s') => {
+            let current = breadcrumbs.last().unwrap();
+            let message = match wait_for(save_for_offline(cacher, current), current) {
+                Some(Ok((saved, total))) => {
+                    format!("Saved for offline with {saved} of {total} images")
+                }
+                Some(Err(err)) => format!("Couldn't save for offline: {err}"),
+                None => String::from("Stopped saving for offline"),
+            };
+            load_link(
+                RStr::from(current.as_ref()),
+                CacheMode::Reuse,
+                htmelements,
+                cacher,
+                forms,
+                verbose,
+            );
+            notice(htmelements, &message);
+            *focused = 0;
+        }
+        KeyCode::Char('d') => {
+            // save what the focused link points to, or the page itself
+            let current = breadcrumbs.last().unwrap();
+            let link = match htmelements[*focused].interaction() {
+                InteractionType::Link(link) => get_link_destination(current, link),
+                _ => RStr::from(current.as_ref()),
+            };
+            if !may_follow(current, &link) {
+                notice(htmelements, LOCAL_FILE_BLOCKED);
+                *focused = 0;
+                return false;
+            }
+            let Some(fetched) = wait_for(start_load(cacher, &link, CacheMode::Reuse), &link) else {
+                return false;
+            };
+            let message = match fetched {
+                Ok(Fetched { url, bytes, .. }) => {
+                    download::save(&download::default_dir(), &download::file_name(&url), &bytes)
+                        .map_or_else(
+                            |err| format!("Couldn't save: {err}"),
+                            |path| format!("Saved to {}", path.display()),
+                        )
+                }
+                Err(FetchError::Download { url, name, size }) => {
+                    match ask_to_download(cacher, &url, &name, size) {
+                        Some(path) => format!(
+                            "Downloading to {}; see about:downloads for how it's going",
+                            path.display()
+                        ),
+                        None => return false,
+                    }
+                }
+                Err(err) => format!("Couldn't save: {err}"),
+            };
+            notice(htmelements, &message);
+            *focused = 0;
+        }
+        KeyCode::Char('b') => {
+            let current = breadcrumbs.last().unwrap();
+            let added = {
+                let mut cacher = cacher.lock().unwrap();
+                // the page's title is whatever it had when it was last visited
+                let title = cacher
+                    .history()
+                    .visits()
+                    .iter()
+                    .rev()
+                    .find(|visit| &visit.url == current)
+                    .map(|visit| visit.title.clone())
+                    .unwrap_or_default();
+                cacher.bookmarks_mut().toggle(current, &title)
+            };
+            load_link(
+                RStr::from(current.as_ref()),
+                CacheMode::Reuse,
+                htmelements,
+                cacher,
+                forms,
+                verbose,
+            );
+            notice(
+                htmelements,
+                if added {
+                    "Bookmarked"
+                } else {
+                    "Bookmark removed"
+                },
+            );
+            *focused = 0;
+        }
+        KeyCode::Char('u') => {
+            let current = breadcrumbs.last().unwrap();
+            let link = current
+                .strip_prefix("view-source:")
+                .map_or_else(|| format!("view-source:{current}"), String::from);
+            if let Some(link) = load_link(
+                link.into(),
+                CacheMode::Reuse,
+                htmelements,
+                cacher,
+                forms,
+                verbose,
+            ) {
+                breadcrumbs.push(String::from(&*link));
+                *focused = 0;
+            }
+        }
         KeyCode::Char('y') => {
             let content = htmelements[*focused].display(false);
             cli_clipboard::set_contents(content).unwrap();
@@ -208,36 +847,429 @@ fn browser_key_event(
             let mut response = String::new();
             std::io::stdin().read_line(&mut response).unwrap();
             enable_raw_mode().unwrap();
-            response = response.trim().to_owned();
-            load_link(RStr::from(response.as_ref()), htmelements, cacher, verbose);
-            breadcrumbs.push(response);
+            // nothing typed means staying put
+            if response.trim().is_empty() {
+                return false;
+            }
+            let search_url = cacher.lock().unwrap().config().search_url.clone();
+            let link = local::path_url(&response)
+                .unwrap_or_else(|| normalize_input(&response, &search_url));
+            if let Some(link) = load_link(
+                RStr::from(link),
+                CacheMode::Normal,
+                htmelements,
+                cacher,
+                forms,
+                verbose,
+            ) {
+                breadcrumbs.push(String::from(&*link));
+            }
+        }
+        _ => {}
+    }
+    false
+}
+
+/// show a message above the page
+fn notice(htmelements: &mut Vec<TerminalLine>, message: &str) {
+    htmelements.insert(
+        0,
+        TerminalLine::from(format!("\x1b[30;47m{message}\x1b[0m")),
+    );
+}
+
+/// handle a key press while the user is typing into a form control; returns whether to submit the form
+fn form_key_event(code: KeyCode, forms: &mut FormState) -> bool {
+    match code {
+        KeyCode::Esc => forms.stop_editing(),
+        KeyCode::Enter => {
+            if forms.editing().is_some_and(|id| forms.is_multiline(id)) {
+                forms.type_char('\n');
+            } else {
+                // pressing enter in a text box submits its form
+                forms.stop_editing();
+                return true;
+            }
         }
+        KeyCode::Backspace => forms.backspace(),
+        KeyCode::Char(c) => forms.type_char(c),
         _ => {}
     }
     false
 }
 
-/// get the link destination and fetch the content on that page
+/// lay a page out again after one of its controls changed, from the elements it was first laid out from
+fn relayout(
+    url: &str,
+    htmelements: &mut Vec<TerminalLine>,
+    cacher: &Arc<Mutex<ByteCacher>>,
+    forms: &mut FormState,
+    verbose: bool,
+) {
+    let Some(document) = forms.take_document() else {
+        return;
+    };
+    forms.clear_layout();
+    // the title was set when the page loaded
+    let mut set_title = SetTitle(RStr::from(url));
+    *htmelements = non_empty(document.display(
+        &mut set_title,
+        cacher,
+        url,
+        TextDirection::Auto,
+        forms,
+        verbose,
+    ));
+    forms.set_document(document);
+}
+
+/// send a form and show the response as a new page
+fn submit_form(
+    submission: &FormSubmission,
+    breadcrumbs: &mut Vec<String>,
+    htmelements: &mut Vec<TerminalLine>,
+    cacher: &Arc<Mutex<ByteCacher>>,
+    forms: &mut BTreeMap<String, FormState>,
+    verbose: bool,
+) {
+    if !may_follow(breadcrumbs.last().unwrap(), submission.action.as_str()) {
+        notice(htmelements, LOCAL_FILE_BLOCKED);
+        return;
+    }
+    let (link, mode) = match submission.method {
+        FormMethod::Get => (submission.url(), CacheMode::Normal),
+        FormMethod::Post => {
+            let (content_type, body) = submission.body();
+            let link = String::from(submission.action.as_str());
+            match wait_for(start_post(cacher, &link, &content_type, body), &link) {
+                // the response is in the cache now, wherever it redirected to
+                Some(Ok(Fetched { url: link, .. })) => (link, CacheMode::Reuse),
+                Some(Err(err)) => {
+                    *htmelements = error_page(&err);
+                    breadcrumbs.push(link);
+                    return;
+                }
+                None => return,
+            }
+        }
+    };
+    if let Some(link) = load_link(link.into(), mode, htmelements, cacher, forms, verbose) {
+        breadcrumbs.push(String::from(&*link));
+    }
+}
+
+/// get the link destination and fetch the content on that page; returns where the page ended up,
+/// or nothing if the user called the load off and the current page stays up
 fn load_link(
     link: RStr,
+    mode: CacheMode,
     htmelements: &mut Vec<TerminalLine>,
     cacher: &Arc<Mutex<ByteCacher>>,
+    forms: &mut BTreeMap<String, FormState>,
     verbose: bool,
-) -> RStr {
-    let mut set_title = SetTitle(link.clone());
-    *htmelements = fetch_html(&link, &mut set_title, cacher, verbose);
+) -> Option<RStr> {
+    let url = String::from(&*link);
+    if let Some(name) = url.strip_prefix("about:") {
+        // the links on `about:downloads` resume and cancel downloads, then show the page again.
+        // Only that page's own links do, so a link somewhere else can't cancel or restart them
+        let (name, action) = name.split_once('?').unwrap_or((name, ""));
+        let url = format!("about:{name}");
+        let previous = cacher.lock().unwrap().begin_page(&url);
+        if name == "downloads" && previous == url {
+            download_action(cacher, action);
+        }
+        *htmelements = about::render(name, &cacher.lock().unwrap(), KEYS);
+        execute!(stdout(), SetTitle(format!("{url} - Nasir"))).unwrap();
+        return Some(url.into());
+    }
+    // the source of a page is whatever's cached for it
+    let source = url.strip_prefix("view-source:");
+    let previous = cacher.lock().unwrap().begin_page(&url);
+    let fetched = match wait_for(start_load(cacher, source.unwrap_or(&url), mode), &url) {
+        Some(Err(FetchError::Input {
+            url,
+            prompt,
+            sensitive,
+        })) => match read_input(&prompt, sensitive) {
+            // the answer goes back to the same url, as its query
+            Some(input) => {
+                return load_link(
+                    utils::with_query(&url, &input).into(),
+                    CacheMode::Normal,
+                    htmelements,
+                    cacher,
+                    forms,
+                    verbose,
+                )
+            }
+            None => None,
+        },
+        // files go to disk, and the page listing downloads shows how they're going
+        Some(Err(FetchError::Download { url, name, size })) => {
+            if ask_to_download(cacher, &url, &name, size).is_some() {
+                return load_link(
+                    "about:downloads".into(),
+                    CacheMode::Reuse,
+                    htmelements,
+                    cacher,
+                    forms,
+                    verbose,
+                );
+            }
+            None
+        }
+        fetched => fetched,
+    };
+    let Some(fetched) = fetched else {
+        if previous.is_empty() {
+            return None;
+        }
+        // starting the load called off the current page's images, so draw it again to get them back
+        load_link(
+            previous.into(),
+            CacheMode::Reuse,
+            htmelements,
+            cacher,
+            forms,
+            verbose,
+        );
+        return None;
+    };
+    let ok = fetched.is_ok();
+    let (link, set_title, lines) = render_page(link, fetched, cacher, forms, verbose);
+    *htmelements = lines;
+    // redrawing a page, or going back to it, isn't another visit
+    if ok && mode != CacheMode::Reuse {
+        let title = set_title.0.strip_suffix(" - Nasir").unwrap_or_default();
+        cacher.lock().unwrap().history_mut().record(&link, title);
+    }
     if verbose {
         print!("{htmelements:#?}\r\n");
     }
     execute!(stdout(), set_title).unwrap();
-    link
+    Some(link)
+}
+
+/// draw the current page again from what's already cached, once something on it arrived in the background;
+/// gives nothing back if the page itself isn't cached, like when it failed to load
+fn redraw(
+    link: &str,
+    cacher: &Arc<Mutex<ByteCacher>>,
+    forms: &mut BTreeMap<String, FormState>,
+    verbose: bool,
+) -> Option<Vec<TerminalLine>> {
+    if let Some(name) = link.strip_prefix("about:") {
+        return Some(non_empty(about::render(
+            name,
+            &cacher.lock().unwrap(),
+            KEYS,
+        )));
+    }
+    let source = link.strip_prefix("view-source:");
+    let response = cacher.lock().unwrap().cached(source.unwrap_or(link))?;
+    let (_, set_title, lines) = render_page(link.into(), Ok(response), cacher, forms, verbose);
+    execute!(stdout(), set_title).unwrap();
+    Some(lines)
+}
+
+/// draw a response as a page, returning the url it's shown under and the window title it should have
+fn render_page(
+    link: RStr,
+    fetched: Result<Fetched, FetchError>,
+    cacher: &Arc<Mutex<ByteCacher>>,
+    forms: &mut BTreeMap<String, FormState>,
+    verbose: bool,
+) -> (RStr, SetTitle<RStr>, Vec<TerminalLine>) {
+    let url = String::from(&*link);
+    let source = url.strip_prefix("view-source:");
+    let mut set_title = SetTitle(link);
+    let (link, lines) = match (source, fetched) {
+        (Some(_), Ok(fetched)) => {
+            let link = RStr::from(format!("view-source:{}", fetched.url));
+            set_title.0 = format!("{link} - Nasir").into();
+            // anything that might be HTML gets coloured
+            let html = fetched.content_type.as_deref().is_none_or(|content_type| {
+                content_type.contains("html") || content_type.contains("xml")
+            });
+            let lines = source::render(
+                &String::from_utf8_lossy(&fetched.bytes),
+                &fetched.url,
+                html,
+                (terminal::size().unwrap().0 - 1) as usize,
+            );
+            (link, lines)
+        }
+        (_, fetched) => fetch_html(&url, fetched, &mut set_title, cacher, forms, verbose),
+    };
+    (link, set_title, non_empty(lines))
+}
+
+/// ask where to save a file, then start downloading it there; returns where it's going, or nothing if the user
+/// gives up with esc
+fn ask_to_download(
+    cacher: &Arc<Mutex<ByteCacher>>,
+    url: &str,
+    name: &str,
+    size: Option<u64>,
+) -> Option<PathBuf> {
+    let default_dir = download::default_dir();
+    let size = size.map_or_else(String::new, |size| {
+        format!(
+            " ({})",
+            format_bytes(usize::try_from(size).unwrap_or(usize::MAX))
+        )
+    });
+    let Some(input) = read_input(
+        &format!("Save {name}{size} to (enter for {})", default_dir.display()),
+        false,
+    ) else {
+        cacher.lock().unwrap().decline_download();
+        return None;
+    };
+    Some(start_download(
+        cacher,
+        url,
+        &download::destination(&input, &default_dir, name),
+    ))
+}
+
+/// resume or cancel a download from a link on `about:downloads`, like `about:downloads?cancel=2`
+fn download_action(cacher: &Arc<Mutex<ByteCacher>>, action: &str) {
+    let Some((action, id)) = action.split_once('=') else {
+        return;
+    };
+    let Ok(id) = id.parse() else {
+        return;
+    };
+    match action {
+        "resume" => {
+            resume_download(cacher, id);
+        }
+        "cancel" => cacher.lock().unwrap().cancel_download(id),
+        _ => {}
+    }
+}
+
+/// wait for a page to load, showing how it's going at the bottom of the screen; returns nothing if the user cancels it
+fn wait_for<T>(loading: Loading<T>, url: &str) -> Option<Result<T, FetchError>> {
+    while !loading.is_finished() {
+        if matches!(event::poll(time::Duration::from_millis(20)), Ok(true)) {
+            if let Ok(event::Event::Key(event::KeyEvent {
+                code,
+                modifiers,
+                kind: event::KeyEventKind::Press,
+                ..
+            })) = event::read()
+            {
+                if code == KeyCode::Esc
+                    || (code == KeyCode::Char('c') && modifiers.contains(KeyModifiers::CONTROL))
+                {
+                    loading.cancel();
+                    return None;
+                }
+            }
+        }
+        let elapsed = loading.started.elapsed();
+        // pages that come right out of the cache shouldn't flash a status line
+        if elapsed > time::Duration::from_millis(100) {
+            let (cols, rows) = terminal::size().unwrap_or((80, 1));
+            let status = format!(
+                ": {} in {:.1}s (esc to cancel)",
+                format_bytes(loading.progress.received()),
+                elapsed.as_secs_f32()
+            );
+            // long urls lose their start so the status stays on one line
+            let url = fit_end(
+                url,
+                usize::from(cols).saturating_sub("Loading ".len() + status.len() + 1),
+            );
+            print!("\x1b[{rows};1H\x1b[2K\x1b[30;47mLoading {url}{status}\x1b[0m");
+            stdout().flush().unwrap();
+        }
+    }
+    Some(loading.finish())
+}
+
+/// ask for a line of text on the bottom of the screen, showing `*`s instead if it's sensitive; returns nothing
+/// if the user gives up with esc
+fn read_input(prompt: &str, sensitive: bool) -> Option<String> {
+    let mut input = String::new();
+    loop {
+        let (cols, rows) = terminal::size().unwrap_or((80, 1));
+        let shown = if sensitive {
+            "*".repeat(input.chars().count())
+        } else {
+            input.clone()
+        };
+        let prompt = if prompt.is_empty() { "Input" } else { prompt };
+        // long answers lose their start so the cursor stays in sight
+        let line = format!("{prompt}: {shown}");
+        let line = fit_end(&line, usize::from(cols).saturating_sub(1));
+        print!("\x1b[{rows};1H\x1b[2K\x1b[30;47m{line}\x1b[0m");
+        stdout().flush().unwrap();
+        let Ok(event::Event::Key(event::KeyEvent {
+            code,
+            modifiers,
+            kind: event::KeyEventKind::Press,
+            ..
+        })) = event::read()
+        else {
+            continue;
+        };
+        match code {
+            KeyCode::Enter => return Some(input),
+            KeyCode::Esc => return None,
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return None,
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Char(c) => input.push(c),
+            _ => {}
+        }
+    }
 }
 
 /// near-pure fn to convert a list of terminal lines to a list of strings within a window
 fn render_lines(lines: &[TerminalLine], focused: usize, verbose: bool) -> Vec<String> {
+    let window = window(lines.len(), focused, verbose);
+    lines[window.clone()]
+        .iter()
+        .zip(window)
+        .map(|(line, i)| line.display(i == focused))
+        .collect()
+}
+
+/// pure fn to give a page with nothing on it a blank line, since the cursor has to be on a line
+fn non_empty(mut lines: Vec<TerminalLine>) -> Vec<TerminalLine> {
+    if lines.is_empty() {
+        lines.push(TerminalLine::from(""));
+    }
+    lines
+}
+
+/// pure fn to find where the focused line went when the page is redrawn, so the cursor stays put when images above it load
+fn refocus(old: &[TerminalLine], new: &[TerminalLine], focused: usize) -> usize {
+    let Some(target) = old.get(focused) else {
+        return focused;
+    };
+    let same = |line: &TerminalLine| match (line.interaction(), target.interaction()) {
+        // an image's lines all change when it loads, but they stay tied to its url
+        (InteractionType::Image(src), InteractionType::Image(target_src)) => src == target_src,
+        _ => line.display(false) == target.display(false),
+    };
+    // the same text can show up more than once, so find the same occurrence of it
+    let occurrence = old[..focused].iter().filter(|line| same(line)).count();
+    new.iter()
+        .enumerate()
+        .filter(|(_, line)| same(line))
+        .nth(occurrence)
+        .map_or(focused, |(idx, _)| idx)
+}
+
+/// near-pure fn to get which lines fit on screen around the focused one
+fn window(max: usize, focused: usize, verbose: bool) -> Range<usize> {
     let mut effective_focus = focused;
     let window_height = terminal::size().unwrap().1 as usize / 2 - 1;
-    let max = lines.len();
     // can't focus past the end of the page
     if effective_focus > max {
         effective_focus = max;
@@ -252,15 +1284,86 @@ fn render_lines(lines: &[TerminalLine], focused: usize, verbose: bool) -> Vec<St
         effective_focus = window_height;
     }
     let start = effective_focus - window_height;
-    let end = effective_focus + window_height;
+    let end = (effective_focus + window_height).min(max);
     if verbose {
         print!("showing window from {start} to {end}; effective focus: {effective_focus}; window height: {window_height}; max: {max}\r\n");
     }
-    lines
-        .iter()
-        .enumerate()
-        .take(end)
-        .skip(start)
-        .map(|(i, line)| line.display(i == focused))
-        .collect()
+    start.min(end)..end
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use clap::Parser;
+
+    use super::{gopher, non_empty, refocus, Args, Config, InteractionType, RStr, TerminalLine};
+
+    #[test]
+    fn refocusing() {
+        let image = |text: &str| {
+            TerminalLine::from(text).with_interaction(InteractionType::Image(RStr::from("a.png")))
+        };
+        let old = vec![
+            TerminalLine::from("intro"),
+            image("[image (loading…)]"),
+            TerminalLine::from("same"),
+            TerminalLine::from("same"),
+        ];
+        let new = vec![
+            TerminalLine::from("intro"),
+            image("####"),
+            image("####"),
+            TerminalLine::from("same"),
+            TerminalLine::from("same"),
+        ];
+        assert_eq!(refocus(&old, &new, 3), 4);
+        assert_eq!(refocus(&old, &new, 1), 1);
+        assert_eq!(refocus(&old, &new, 0), 0);
+    }
+
+    #[test]
+    fn proxy_precedence() {
+        let env = BTreeMap::from([
+            ("HTTP_PROXY", "http://env-proxy:3128"),
+            ("https_proxy", "http://env-proxy:3129"),
+            ("NO_PROXY", "localhost"),
+        ]);
+        let config = |flags: &[&str]| {
+            let mut config: Config =
+                toml::from_str(r#"https-proxy = "http://file-proxy:8080""#).unwrap();
+            config.proxy_from_env(|name| env.get(name).map(|value| String::from(*value)));
+            Args::parse_from([&["nasir"], flags].concat()).apply(&mut config);
+            config
+        };
+        // the config file wins over the environment
+        let config_only = config(&[]);
+        assert_eq!(
+            config_only.https_proxy.as_deref(),
+            Some("http://file-proxy:8080")
+        );
+        assert_eq!(
+            config_only.http_proxy.as_deref(),
+            Some("http://env-proxy:3128")
+        );
+        // and a proxy on the command line wins over both, for every scheme
+        let flagged = config(&[
+            "--proxy",
+            "socks5h://localhost:9050",
+            "--no-proxy",
+            ".internal",
+        ]);
+        assert_eq!(flagged.proxy.as_deref(), Some("socks5h://localhost:9050"));
+        assert_eq!(flagged.http_proxy, None);
+        assert_eq!(flagged.https_proxy, None);
+        assert_eq!(flagged.no_proxy, [".internal"]);
+    }
+
+    #[test]
+    fn empty_pages() {
+        // an empty menu, text file or gemtext page has no lines of its own, which left nowhere for the cursor
+        assert!(gopher::render_menu("").is_empty());
+        assert_eq!(non_empty(gopher::render_menu("")).len(), 1);
+        assert_eq!(non_empty(vec![TerminalLine::from("a")]).len(), 1);
+    }
 }
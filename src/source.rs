@@ -0,0 +1,353 @@
+//! Showing a response as it came, with numbered lines and its HTML coloured in
+
+use std::fmt::Write;
+
+use lazy_regex::lazy_regex;
+use unicode_width::UnicodeWidthChar;
+use url::Url;
+
+use crate::{types::prelude::*, utils::transform_html_text};
+
+/// what part of the HTML a piece of text is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Text,
+    Tag,
+    Attribute,
+    Value,
+    Entity,
+    Comment,
+}
+
+impl Kind {
+    /// pure fn to get the colour a kind of text is drawn in
+    const fn color(self) -> &'static str {
+        match self {
+            Self::Text => "0",
+            Self::Tag => "95",
+            Self::Attribute => "33",
+            Self::Value => "32",
+            Self::Entity => "36",
+            Self::Comment => "90",
+        }
+    }
+}
+
+/// a piece of the source; `link` is set on the values of `href` and `src` attributes
+#[derive(Debug, PartialEq, Eq)]
+struct Token<'a> {
+    kind: Kind,
+    text: &'a str,
+    link: bool,
+}
+
+impl<'a> Token<'a> {
+    const fn new(kind: Kind, text: &'a str) -> Self {
+        Self {
+            kind,
+            text,
+            link: false,
+        }
+    }
+}
+
+/// pure fn to split HTML into the pieces that get coloured differently
+fn tokenize(html: &str) -> Vec<Token<'_>> {
+    let tag_start = lazy_regex!("^<[a-zA-Z/!?]");
+    let entity = lazy_regex!("^&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z][a-zA-Z0-9]*);");
+    let mut tokens = Vec::new();
+    let mut rest = html;
+    while !rest.is_empty() {
+        let len = if let Some(comment) = rest.strip_prefix("<!--") {
+            let end = comment.find("-->").map_or(rest.len(), |end| end + 7);
+            tokens.push(Token::new(Kind::Comment, &rest[..end]));
+            end
+        } else if tag_start.is_match(rest) {
+            tag(rest, &mut tokens)
+        } else if let Some(entity) = entity.find(rest) {
+            tokens.push(Token::new(Kind::Entity, entity.as_str()));
+            entity.end()
+        } else {
+            // text goes up to whatever could start something else
+            let end = rest
+                .char_indices()
+                .skip(1)
+                .find(|&(_, c)| c == '<' || c == '&')
+                .map_or(rest.len(), |(idx, _)| idx);
+            tokens.push(Token::new(Kind::Text, &rest[..end]));
+            end
+        };
+        rest = &rest[len..];
+    }
+    tokens
+}
+
+/// read a tag and its attributes off the start of `html`, along with everything in it if it's a script or
+/// style, since that isn't HTML; returns how much it read
+fn tag<'a>(html: &'a str, tokens: &mut Vec<Token<'a>>) -> usize {
+    let name_end = html
+        .char_indices()
+        .skip(1)
+        .find(|&(idx, c)| c.is_whitespace() || c == '>' || (c == '/' && idx > 1))
+        .map_or(html.len(), |(idx, _)| idx);
+    tokens.push(Token::new(Kind::Tag, &html[..name_end]));
+    let name = html[1..name_end].to_ascii_lowercase();
+    let mut pos = name_end;
+    let mut attribute = "";
+    let mut expecting_value = false;
+    loop {
+        let rest = &html[pos..];
+        let Some(c) = rest.chars().next() else {
+            // the page ended in the middle of the tag
+            return pos;
+        };
+        let token = if rest.starts_with("/>") {
+            tokens.push(Token::new(Kind::Tag, "/>"));
+            return pos + 2;
+        } else if c == '>' {
+            tokens.push(Token::new(Kind::Tag, ">"));
+            pos += 1;
+            break;
+        } else if c.is_whitespace() {
+            let end = rest
+                .find(|c: char| !c.is_whitespace())
+                .unwrap_or(rest.len());
+            Token::new(Kind::Text, &rest[..end])
+        } else if c == '=' {
+            expecting_value = true;
+            Token::new(Kind::Text, "=")
+        } else if expecting_value {
+            expecting_value = false;
+            let end = if c == '"' || c == '\'' {
+                rest[1..].find(c).map_or(rest.len(), |end| end + 2)
+            } else {
+                rest.find(|c: char| c.is_whitespace() || c == '>')
+                    .unwrap_or(rest.len())
+            };
+            Token {
+                kind: Kind::Value,
+                text: &rest[..end],
+                link: attribute.eq_ignore_ascii_case("href")
+                    || attribute.eq_ignore_ascii_case("src"),
+            }
+        } else if c == '/' {
+            Token::new(Kind::Text, "/")
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || matches!(c, '=' | '>' | '/'))
+                .unwrap_or(rest.len());
+            attribute = &rest[..end];
+            Token::new(Kind::Attribute, attribute)
+        };
+        pos += token.text.len();
+        tokens.push(token);
+    }
+    if matches!(name.as_str(), "script" | "style") {
+        // lowercasing only changes ascii letters, so the positions stay the same
+        let end = html[pos..]
+            .to_ascii_lowercase()
+            .find(&format!("</{name}"))
+            .map_or(html.len(), |end| pos + end);
+        if end > pos {
+            tokens.push(Token::new(Kind::Text, &html[pos..end]));
+        }
+        pos = end;
+    }
+    pos
+}
+
+/// pure fn to get where an attribute's value points, from the page at `base`
+fn destination(base: Option<&Url>, value: &str) -> Option<RStr> {
+    let value = transform_html_text(value.trim_matches(['"', '\'']));
+    let value = value.trim();
+    if value.is_empty() || value.starts_with("javascript:") {
+        return None;
+    }
+    let url = base.map_or_else(|| Url::parse(value), |base| base.join(value));
+    url.ok().map(|url| String::from(url).into())
+}
+
+/// the page being drawn, one row at a time
+struct Layout {
+    lines: Vec<TerminalLine>,
+    /// the number of the line of source the row is part of
+    number: usize,
+    /// how wide the line numbers are
+    digits: usize,
+    /// how wide a row can be after its line number
+    width: usize,
+    row: String,
+    row_width: usize,
+    /// where the row goes when it's selected
+    link: Option<RStr>,
+    /// whether the row carries on a line that was too long for the one above
+    continued: bool,
+}
+
+impl Layout {
+    /// add a piece of source to the end of the page, wrapping it onto new rows as they fill up
+    fn push(&mut self, kind: Kind, text: &str, link: Option<&RStr>) {
+        let mut run = String::new();
+        for c in text.replace('\t', "    ").chars() {
+            // control characters would move the cursor or colour the rest of the screen
+            if c.is_control() {
+                continue;
+            }
+            let width = c.width().unwrap_or(0);
+            if self.row_width + width > self.width && self.row_width > 0 {
+                self.paint(kind, &run, link);
+                run.clear();
+                self.end_row();
+                self.continued = true;
+            }
+            run.push(c);
+            self.row_width += width;
+        }
+        self.paint(kind, &run, link);
+    }
+
+    /// add a run of text to the row in its colour; the first link on a row is the one it follows
+    fn paint(&mut self, kind: Kind, run: &str, link: Option<&RStr>) {
+        if run.is_empty() {
+            return;
+        }
+        let color = match link {
+            Some(link) if self.link.as_ref().is_none_or(|row_link| row_link == link) => {
+                self.link = Some(link.clone());
+                "4;94"
+            }
+            _ => kind.color(),
+        };
+        if color == "0" {
+            self.row.push_str(run);
+        } else {
+            write!(self.row, "\x1b[{color}m{run}\x1b[0m").unwrap();
+        }
+    }
+
+    /// put the row on the page, behind its line number
+    fn end_row(&mut self) {
+        let gutter = if self.continued {
+            " ".repeat(self.digits + 2)
+        } else {
+            format!(
+                "\x1b[2m{:>digits$}\x1b[0m  ",
+                self.number,
+                digits = self.digits
+            )
+        };
+        let numbered = TerminalLine::from(format!("{gutter}{}", std::mem::take(&mut self.row)));
+        self.lines.push(match self.link.take() {
+            Some(link) => numbered.with_interaction(InteractionType::Link(link)),
+            None => numbered,
+        });
+        self.row_width = 0;
+    }
+
+    /// finish a line of source
+    fn end_line(&mut self) {
+        self.end_row();
+        self.number += 1;
+        self.continued = false;
+    }
+}
+
+/// pure fn to draw a response as it came in rows of at most `width`, with its lines numbered; HTML gets coloured,
+/// and `href` and `src` values link to where they point from `url`
+pub fn render(body: &str, url: &str, html: bool, width: usize) -> Vec<TerminalLine> {
+    let tokens = if html {
+        tokenize(body)
+    } else {
+        vec![Token::new(Kind::Text, body)]
+    };
+    let base = Url::parse(url).ok();
+    let digits = body.lines().count().max(1).to_string().len();
+    let mut layout = Layout {
+        lines: Vec::new(),
+        number: 1,
+        digits,
+        width: width.saturating_sub(digits + 2).max(1),
+        row: String::new(),
+        row_width: 0,
+        link: None,
+        continued: false,
+    };
+    for token in &tokens {
+        let link = token
+            .link
+            .then(|| destination(base.as_ref(), token.text))
+            .flatten();
+        for (idx, piece) in token.text.split('\n').enumerate() {
+            if idx > 0 {
+                layout.end_line();
+            }
+            layout.push(token.kind, piece, link.as_ref());
+        }
+    }
+    // a newline at the end doesn't start another line, but the page can't be empty
+    if !layout.row.is_empty() || layout.lines.is_empty() {
+        layout.end_row();
+    }
+    layout.lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render, tokenize, Kind, Token};
+    use crate::types::prelude::*;
+
+    #[test]
+    fn highlighting() {
+        let tokens = tokenize(
+            "<a href=\"/x?a=1&amp;b\">&lt;hi&gt;</a><!-- note --><script>if (a<b) {}</script>",
+        );
+        let kinds: Vec<(Kind, &str)> = tokens
+            .iter()
+            .map(|Token { kind, text, .. }| (*kind, *text))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (Kind::Tag, "<a"),
+                (Kind::Text, " "),
+                (Kind::Attribute, "href"),
+                (Kind::Text, "="),
+                (Kind::Value, "\"/x?a=1&amp;b\""),
+                (Kind::Tag, ">"),
+                (Kind::Entity, "&lt;"),
+                (Kind::Text, "hi"),
+                (Kind::Entity, "&gt;"),
+                (Kind::Tag, "</a"),
+                (Kind::Tag, ">"),
+                (Kind::Comment, "<!-- note -->"),
+                (Kind::Tag, "<script"),
+                (Kind::Tag, ">"),
+                (Kind::Text, "if (a<b) {}"),
+                (Kind::Tag, "</script"),
+                (Kind::Tag, ">"),
+            ]
+        );
+        assert!(tokens[4].link);
+        let lines = render(
+            "<p>\n<img src=pic.png alt=\"a picture\">\n</p>\n",
+            "https://example.com/dir/page.html",
+            true,
+            20,
+        );
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[1].interaction(),
+            &InteractionType::Link(RStr::from("https://example.com/dir/pic.png"))
+        );
+        assert!(lines[1]
+            .display(false)
+            .starts_with(" \x1b[2m2\x1b[0m  \x1b[95m<img"));
+        // the line that was too long carries on without a number
+        assert!(lines[2].display(false).starts_with("    \x1b[33malt"));
+        assert_eq!(
+            lines[3].display(false),
+            " \x1b[2m3\x1b[0m  \x1b[95m</p\x1b[0m\x1b[95m>\x1b[0m"
+        );
+        assert_eq!(render("", "", false, 30).len(), 1);
+    }
+}
@@ -0,0 +1,135 @@
+//! Certificates: the ones Nasir trusts and presents, and explaining the ones it doesn't trust
+
+use std::{error::Error, fs, net::TcpStream, path::Path};
+
+use reqwest::{tls::Version, Certificate, ClientBuilder, Identity};
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+
+/// what the user needs to decide whether to trust a certificate anyway
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificateInfo {
+    pub issuer: String,
+    /// sha-256 of the certificate, as colon-separated hex
+    pub fingerprint: String,
+}
+
+/// apply the config's root certificates, client certificate and minimum TLS version to a client
+pub fn configure(mut builder: ClientBuilder, config: &Config) -> Result<ClientBuilder, String> {
+    for path in &config.ca_certificates {
+        for cert in Certificate::from_pem_bundle(&read(path)?)
+            .map_err(|err| format!("Certificate Error in {}: {err}", path.display()))?
+        {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    match (&config.client_certificate, &config.client_key) {
+        (Some(cert), Some(key)) => {
+            let identity = Identity::from_pkcs8_pem(&read(cert)?, &read(key)?)
+                .map_err(|err| format!("Certificate Error in {}: {err}", cert.display()))?;
+            builder = builder.identity(identity);
+        }
+        (None, None) => {}
+        _ => {
+            return Err(String::from(
+                "Config Error: client-certificate and client-key go together",
+            ))
+        }
+    }
+    if let Some(version) = &config.min_tls_version {
+        builder = builder.min_tls_version(parse_version(version)?);
+    }
+    Ok(builder)
+}
+
+/// read a certificate or key file
+pub fn read(path: &Path) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|err| format!("Couldn't read {}: {err}", path.display()))
+}
+
+/// pure fn to read a TLS version like `1.2`
+pub fn parse_version(version: &str) -> Result<Version, String> {
+    match version.trim().trim_start_matches("TLS").trim() {
+        "1.0" | "1" => Ok(Version::TLS_1_0),
+        "1.1" => Ok(Version::TLS_1_1),
+        "1.2" => Ok(Version::TLS_1_2),
+        "1.3" => Ok(Version::TLS_1_3),
+        _ => Err(format!(
+            "Config Error: min-tls-version should be 1.0, 1.1, 1.2 or 1.3, not `{version}`"
+        )),
+    }
+}
+
+/// pure fn to find why a request failed, if it was the TLS handshake that failed
+pub fn handshake_error(err: &(dyn Error + 'static)) -> Option<String> {
+    let mut source = Some(err);
+    while let Some(inner) = source {
+        if let Some(tls) = inner.downcast_ref::<native_tls::Error>() {
+            let reason = tls.to_string();
+            // openssl buries the useful part in parentheses at the end
+            return Some(
+                reason
+                    .rsplit_once(" (")
+                    .and_then(|(_, reason)| reason.strip_suffix(')'))
+                    .map_or_else(|| reason.clone(), String::from),
+            );
+        }
+        source = inner.source();
+    }
+    None
+}
+
+/// go through the handshake with a host again without checking its certificate, to see what the certificate says
+pub fn inspect(host: &str, stream: TcpStream) -> Option<CertificateInfo> {
+    let connector = native_tls::TlsConnector::builder()
+        .danger_accept_invalid_certs(true)
+        .danger_accept_invalid_hostnames(true)
+        .build()
+        .ok()?;
+    let der = connector
+        .connect(host, stream)
+        .ok()?
+        .peer_certificate()
+        .ok()??
+        .to_der()
+        .ok()?;
+    Some(describe(&der))
+}
+
+/// pure fn to get what the user needs to know about a DER-encoded certificate
+pub fn describe(der: &[u8]) -> CertificateInfo {
+    CertificateInfo {
+        issuer: x509_parser::parse_x509_certificate(der)
+            .map(|(_, cert)| cert.issuer().to_string())
+            .unwrap_or_default(),
+        fingerprint: fingerprint(der),
+    }
+}
+
+/// pure fn to get a certificate's sha-256 fingerprint the way browsers show it
+pub fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der)
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::tls::Version;
+
+    use super::{fingerprint, parse_version};
+
+    #[test]
+    fn settings() {
+        assert_eq!(parse_version("1.2"), Ok(Version::TLS_1_2));
+        assert_eq!(parse_version("TLS 1.3"), Ok(Version::TLS_1_3));
+        assert!(parse_version("2.0").is_err());
+        assert_eq!(
+            fingerprint(b""),
+            "E3:B0:C4:42:98:FC:1C:14:9A:FB:F4:C8:99:6F:B9:24:27:AE:41:E4:64:9B:93:4C:A4:95:99:1B:78:52:B8:55"
+        );
+    }
+}
@@ -1,35 +1,163 @@
 use std::{
+    cell::Cell,
     collections::BTreeMap,
     sync::{Arc, Mutex},
 };
 
 use crossterm::terminal::{self, SetTitle};
+use unicode_bidi::{ParagraphBidiInfo, LTR_LEVEL, RTL_LEVEL};
 
 use crate::{
-    cacher::{self, ByteCacher},
+    cacher::{self, ByteCacher, Resource},
     get_link_destination, img,
-    utils::wrap,
+    utils::{display_width, fit_end, may_follow, reorder_bidi, starts_rtl, wrap, ZERO_WIDTH_SPACE},
 };
 
-use super::{InteractionType, RStr, TerminalLine};
+use super::{ControlKind, ControlValue, FormState, InteractionType, RStr, TerminalLine};
 
 #[derive(Debug, PartialEq)]
 pub enum DocElement {
     HtmlElement {
         name: RStr,
-        children: Vec<DocElement>,
+        children: Vec<Self>,
         properties: BTreeMap<RStr, RStr>,
     },
     ClosingTag(RStr),
     Text(RStr),
 }
 
+/// which way text flows, from the `dir` and `lang` attributes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextDirection {
+    Ltr,
+    Rtl,
+    /// go off the first strong character of each paragraph
+    Auto,
+}
+
+impl TextDirection {
+    /// languages that are written right-to-left
+    const RTL_LANGS: &'static [&'static str] =
+        &["ar", "arc", "dv", "fa", "he", "ps", "sd", "ug", "ur", "yi"];
+
+    /// pure fn to get the direction of an element, given its parent's direction
+    fn of_element(properties: &BTreeMap<RStr, RStr>, inherited: Self) -> Self {
+        match properties
+            .get("dir")
+            .map(|dir| dir.to_lowercase())
+            .as_deref()
+        {
+            Some("rtl") => Self::Rtl,
+            Some("ltr") => Self::Ltr,
+            Some("auto") => Self::Auto,
+            _ => properties.get("lang").map_or(inherited, |lang| {
+                let primary = lang.split(['-', '_']).next().unwrap_or("").to_lowercase();
+                if Self::RTL_LANGS.contains(&primary.as_str()) {
+                    Self::Rtl
+                } else {
+                    inherited
+                }
+            }),
+        }
+    }
+
+    /// pure fn to decide if a paragraph of text is right-to-left
+    fn is_rtl(self, txt: &str) -> bool {
+        match self {
+            Self::Ltr => false,
+            Self::Rtl => true,
+            Self::Auto => starts_rtl(txt),
+        }
+    }
+}
+
+/// elements that flow along with the text around them instead of starting a block of their own
+const INLINE_ELEMENTS: &[&str] = &[
+    "a", "abbr", "b", "bdi", "bdo", "big", "button", "cite", "code", "data", "dfn", "em", "font",
+    "i", "img", "input", "kbd", "label", "mark", "q", "s", "samp", "select", "small", "span",
+    "strong", "sub", "sup", "textarea", "time", "tt", "u", "var",
+];
+
+/// what an element is laid out in
+#[derive(Clone, Copy)]
+struct Flow<'a> {
+    dir: TextDirection,
+    /// how many columns the lines can take up
+    width: usize,
+    /// the paragraph the element's text is part of, if it's inside a block
+    paragraph: Option<&'a Paragraph<'a>>,
+}
+
+/// the text of a block, with its direction resolved all at once so runs can cross inline elements
+struct Paragraph<'a> {
+    bidi: ParagraphBidiInfo<'a>,
+    /// how far into the text the lines laid out so far go
+    cursor: Cell<usize>,
+}
+
+impl Paragraph<'_> {
+    /// pure fn to check if the paragraph is right-to-left
+    fn is_rtl(&self) -> bool {
+        self.bidi.paragraph_level.is_rtl()
+    }
+
+    /// reorder the next line of the paragraph into the order it's displayed in, if it's the next line
+    fn reorder(&self, line: &str) -> Option<String> {
+        let cursor = self.cursor.get();
+        let start = cursor + self.bidi.text.get(cursor..)?.find(line)?;
+        let end = start + line.len();
+        self.cursor.set(end);
+        Some(self.bidi.reorder_line(start..end).into_owned())
+    }
+}
+
 impl DocElement {
     pub fn display(
         &self,
         set_title: &mut SetTitle<RStr>,
         cacher: &Arc<Mutex<ByteCacher>>,
         link: &str,
+        dir: TextDirection,
+        forms: &mut FormState,
+        verbose: bool,
+    ) -> Vec<TerminalLine> {
+        let flow = Flow {
+            dir,
+            width: (terminal::size().unwrap().0 - 1) as usize,
+            paragraph: None,
+        };
+        // text on its own is laid out like a block with nothing else in it
+        if self.is_inline() {
+            layout_block(
+                std::slice::from_ref(self),
+                set_title,
+                cacher,
+                link,
+                flow,
+                forms,
+                verbose,
+            )
+        } else {
+            self.layout(set_title, cacher, link, flow, forms, verbose)
+        }
+    }
+
+    /// pure fn to check if an element flows along with the text around it
+    fn is_inline(&self) -> bool {
+        match self {
+            Self::HtmlElement { name, .. } => INLINE_ELEMENTS.contains(&name.as_ref()),
+            Self::Text(_) | Self::ClosingTag(_) => true,
+        }
+    }
+
+    /// lay out an element in the space and paragraph it's in
+    fn layout(
+        &self,
+        set_title: &mut SetTitle<RStr>,
+        cacher: &Arc<Mutex<ByteCacher>>,
+        link: &str,
+        flow: Flow,
+        forms: &mut FormState,
         verbose: bool,
     ) -> Vec<TerminalLine> {
         match self {
@@ -52,7 +180,9 @@ impl DocElement {
                                 let title = children
                                     .iter()
                                     // get the terminal lines
-                                    .flat_map(|tl| tl.display(set_title, cacher, link, verbose))
+                                    .flat_map(|tl| {
+                                        tl.layout(set_title, cacher, link, flow, forms, verbose)
+                                    })
                                     // get the text
                                     .map(|tl| tl.display(false))
                                     // make it into a string
@@ -69,12 +199,34 @@ impl DocElement {
                 // this is for elements that shouldn't display anything under them
                 "script" | "style" | "option" => Vec::new(),
                 "img" => display_img(properties, cacher, link, verbose),
+                "input" => display_input(properties, forms),
+                "textarea" => display_textarea(properties, &self.text_content(), forms),
+                "select" => display_select(properties, children, forms),
+                "button" => display_button(properties, &self.text_content(), forms),
                 _ => {
-                    let ret: Vec<TerminalLine> = children
-                        .iter()
-                        .flat_map(|tl| tl.display(set_title, cacher, link, verbose))
-                        .filter(|tl| !tl.is_empty())
-                        .collect();
+                    let flow = Flow {
+                        dir: TextDirection::of_element(properties, flow.dir),
+                        width: flow.width.saturating_sub(decoration_width(name)),
+                        paragraph: flow.paragraph,
+                    };
+                    let outer_form = (&**name == "form").then(|| forms.open_form(properties));
+                    let outer_fieldset =
+                        (&**name == "fieldset").then(|| forms.open_fieldset(properties));
+                    let ret: Vec<TerminalLine> = if self.is_inline() {
+                        children
+                            .iter()
+                            .flat_map(|tl| tl.layout(set_title, cacher, link, flow, forms, verbose))
+                            .filter(|tl| !tl.is_empty())
+                            .collect()
+                    } else {
+                        layout_block(children, set_title, cacher, link, flow, forms, verbose)
+                    };
+                    if let Some(outer_fieldset) = outer_fieldset {
+                        forms.close_fieldset(outer_fieldset);
+                    }
+                    if let Some(outer_form) = outer_form {
+                        forms.close_form(outer_form);
+                    }
                     let ret = display_formatted_element(name, properties, ret);
                     match properties.get("id") {
                         Some(id) => ret.into_iter().map(|tl| tl.with_id(id.clone())).collect(),
@@ -82,17 +234,84 @@ impl DocElement {
                     }
                 }
             },
-            Self::Text(txt) => wrap(txt, (terminal::size().unwrap().0 - 1) as usize)
-                .into_iter()
-                .map(|str| TerminalLine::from(&str[1..]))
-                .collect(),
+            Self::Text(txt) => {
+                let rtl = flow
+                    .paragraph
+                    .map_or_else(|| flow.dir.is_rtl(txt), Paragraph::is_rtl);
+                wrap(txt, flow.width)
+                    .into_iter()
+                    .map(|line| {
+                        flow.paragraph
+                            .and_then(|paragraph| paragraph.reorder(&line))
+                            .unwrap_or_else(|| reorder_bidi(&line, rtl))
+                    })
+                    .map(TerminalLine::from)
+                    .collect()
+            }
             Self::ClosingTag(_) => vec![RStr::from("").into()],
         }
     }
 
+    /// pure function to get the lines of text an element adds to the paragraph it's in, as they're wrapped
+    fn paragraph_lines(&self, width: usize, lines: &mut Vec<String>) {
+        match self {
+            Self::HtmlElement { name, children, .. }
+                // these draw their text some other way, if at all
+                if !matches!(
+                    name.as_ref(),
+                    "script" | "style" | "option" | "img" | "input" | "textarea" | "select" | "button"
+                ) =>
+            {
+                for child in children.iter().filter(|child| child.is_inline()) {
+                    child.paragraph_lines(width, lines);
+                }
+            }
+            Self::Text(txt) => lines.extend(wrap(txt, width)),
+            Self::HtmlElement { .. } | Self::ClosingTag(_) => {}
+        }
+    }
+
+    /// pure function to get all the text inside an element
+    fn text_content(&self) -> String {
+        match self {
+            Self::HtmlElement { children, .. } => children
+                .iter()
+                .map(Self::text_content)
+                .collect::<Vec<_>>()
+                .join(" "),
+            Self::Text(txt) => txt.to_string(),
+            Self::ClosingTag(_) => String::new(),
+        }
+    }
+
+    /// pure function to get the `src` of every image inside an element
+    pub fn image_sources(&self) -> Vec<RStr> {
+        match self {
+            Self::HtmlElement {
+                name,
+                children,
+                properties,
+            } => {
+                let mut sources: Vec<RStr> = properties
+                    .get("src")
+                    .filter(|_| &**name == "img")
+                    .cloned()
+                    .into_iter()
+                    .collect();
+                sources.extend(children.iter().flat_map(Self::image_sources));
+                sources
+            }
+            Self::Text(_) | Self::ClosingTag(_) => Vec::new(),
+        }
+    }
+
     /// pure function to collapse some elements and so on
     pub fn minify(self) -> Self {
         match self {
+            // `<wbr>` only marks a place the surrounding text may break
+            Self::HtmlElement { name, .. } if &*name == "wbr" => {
+                Self::Text(RStr::from(ZERO_WIDTH_SPACE.to_string()))
+            }
             Self::HtmlElement {
                 name,
                 children,
@@ -107,7 +326,7 @@ impl DocElement {
                         Self::Text(txt) => !txt.trim().is_empty(),
                         _ => true,
                     })
-                    .collect(),
+                    .fold(Vec::new(), join_word_breaks),
                 properties,
             },
             Self::Text(txt) => Self::Text(RStr::from(txt.trim())),
@@ -116,6 +335,79 @@ impl DocElement {
     }
 }
 
+/// lay out the children of a block: the text in it is one paragraph, aligned to the side it starts from,
+/// and the blocks in it are laid out on their own
+fn layout_block(
+    children: &[DocElement],
+    set_title: &mut SetTitle<RStr>,
+    cacher: &Arc<Mutex<ByteCacher>>,
+    link: &str,
+    flow: Flow,
+    forms: &mut FormState,
+    verbose: bool,
+) -> Vec<TerminalLine> {
+    let mut lines = Vec::new();
+    for child in children.iter().filter(|child| child.is_inline()) {
+        child.paragraph_lines(flow.width, &mut lines);
+    }
+    let text = lines.join(" ");
+    let level = if flow.dir.is_rtl(&text) {
+        RTL_LEVEL
+    } else {
+        LTR_LEVEL
+    };
+    let paragraph = Paragraph {
+        bidi: ParagraphBidiInfo::new(&text, Some(level)),
+        cursor: Cell::new(0),
+    };
+    let mut ret = Vec::new();
+    for child in children {
+        if child.is_inline() {
+            let inline = Flow {
+                paragraph: Some(&paragraph),
+                ..flow
+            };
+            let lines = child.layout(set_title, cacher, link, inline, forms, verbose);
+            if paragraph.is_rtl() {
+                ret.extend(lines.into_iter().map(|tl| tl.visible_left_pad(flow.width)));
+            } else {
+                ret.extend(lines);
+            }
+        } else {
+            let block = Flow {
+                paragraph: None,
+                ..flow
+            };
+            ret.extend(child.layout(set_title, cacher, link, block, forms, verbose));
+        }
+    }
+    ret.retain(|tl| !tl.is_empty());
+    ret
+}
+
+/// pure fn to get how many columns an element's formatting adds around its lines
+fn decoration_width(name: &str) -> usize {
+    match name {
+        "h1" | "h2" | "h3" => 2,
+        "h5" => "#### ".len() + " ####".len(),
+        "h6" => "## ".len() + " ##".len(),
+        _ => 0,
+    }
+}
+
+/// pure function to glue text on either side of a `<wbr>` back into one word
+fn join_word_breaks(mut children: Vec<DocElement>, next: DocElement) -> Vec<DocElement> {
+    match (children.last_mut(), next) {
+        (Some(DocElement::Text(prev)), DocElement::Text(txt))
+            if prev.ends_with(ZERO_WIDTH_SPACE) || txt.starts_with(ZERO_WIDTH_SPACE) =>
+        {
+            *prev = format!("{prev}{txt}").into();
+        }
+        (_, next) => children.push(next),
+    }
+    children
+}
+
 /// pure function to apply special formatting to the output of `DocElement::display`
 #[allow(clippy::too_many_lines)]
 fn display_formatted_element(
@@ -125,19 +417,16 @@ fn display_formatted_element(
 ) -> Vec<TerminalLine> {
     match name {
         "a" => {
-            let href: RStr = properties.get("href").map_or("".into(), Clone::clone);
+            let href: RStr = properties
+                .get("href")
+                .map_or_else(|| "".into(), Clone::clone);
             ret.into_iter()
                 .map(|content| {
                     // if it's already a link, prefer the lower-level one
                     if let InteractionType::Link(_) = content.interaction() {
                         content
                     } else {
-                        content
-                            // >(text)[link] when focused
-                            .map_focused(|str| format!("({str})[\x1b[94m{href}\x1b[0m]").into())
-                            // blue underlined link when unfocused
-                            .map_unfocused(|str| format!("\x1b[4;94m{str}\x1b[0m").into())
-                            .with_interaction(InteractionType::Link(href.clone()))
+                        content.into_link(href.clone())
                     }
                 })
                 .collect()
@@ -230,49 +519,320 @@ fn display_img(
     verbose: bool,
 ) -> Vec<TerminalLine> {
     let src = properties.get("src");
-    src.and_then(|src| {
-        cacher::get_from_cache(
-            cacher.clone(),
-            &get_link_destination(base_link, src),
-            verbose,
-        )
-    })
-    .and_then(|img_bytes| {
-        // if we can get an image, return it
-        if verbose {
-            print!("`display_img` Got something from cache\r\n");
-        }
-        img::get_image(&img_bytes).map_or(None, |img| {
+    let url = src
+        .map(|src| get_link_destination(base_link, src))
+        .filter(|url| may_follow(base_link, url));
+    let resource = url.as_ref().map_or(Resource::Missing, |url| {
+        cacher::get_from_cache(cacher, url, verbose)
+    });
+    let status = match resource {
+        Resource::Ready(img_bytes) => {
+            // if we can get an image, return it
             if verbose {
-                print!("The thing `display_img` got from cache worked\r\n");
+                print!("`display_img` Got something from cache\r\n");
             }
-            let img = img::approximate_image(
-                &img,
-                {
-                    let size = terminal::size().unwrap();
-                    ((size.0 / 3 * 2).into(), (size.1 / 3 * 2).into())
-                },
-                verbose,
-            );
-            if let Some(src) = src {
-                Some(
-                    img.into_iter()
-                        .map(|tl| tl.with_interaction(InteractionType::Image(src.clone())))
+            if let Ok(img) = img::get_image(&img_bytes) {
+                if verbose {
+                    print!("The thing `display_img` got from cache worked\r\n");
+                }
+                let img = img::approximate_image(
+                    &img,
+                    {
+                        let size = terminal::size().unwrap();
+                        ((size.0 / 3 * 2).into(), (size.1 / 3 * 2).into())
+                    },
+                    verbose,
+                );
+                return match url {
+                    Some(url) => img
+                        .into_iter()
+                        .map(|tl| tl.with_interaction(InteractionType::Image(url.clone())))
                         .collect(),
-                )
-            } else {
-                Some(img)
+                    None => img,
+                };
             }
+            None
+        }
+        Resource::Loading => Some(String::from("loading…")),
+        Resource::Failed(err) => Some(format!("failed: {}", err.summary())),
+        Resource::Missing => None,
+    };
+    let alt: &str = properties.get("alt").or(src).map_or("", |alt| alt);
+    let label = match (alt.is_empty(), status) {
+        (true, None) => String::from("[image]"),
+        (true, Some(status)) => format!("[image ({status})]"),
+        (false, None) => format!("[image: {alt}]"),
+        (false, Some(status)) => format!("[image: {alt} ({status})]"),
+    };
+    let line = TerminalLine::from(RStr::from(label));
+    vec![match url {
+        // so the cacher knows when the placeholder is on screen
+        Some(url) => line.with_interaction(InteractionType::Image(url)),
+        None => line,
+    }]
+}
+
+/// pure fn to get an attribute, or an empty string if it isn't there
+fn attribute(properties: &BTreeMap<RStr, RStr>, key: &str) -> RStr {
+    properties.get(key).map_or_else(|| "".into(), Clone::clone)
+}
+
+/// pure fn to get a numeric attribute like `size` or `rows`
+fn numeric_attribute(properties: &BTreeMap<RStr, RStr>, key: &str, default: usize) -> usize {
+    properties
+        .get(key)
+        .and_then(|value| value.trim().parse().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(default)
+}
+
+/// pure fn to draw a text box `width` columns wide, showing the end of the text if it doesn't fit
+fn text_field(text: &str, placeholder: &str, width: usize, editing: bool) -> String {
+    if text.is_empty() && !editing {
+        let shown = fit_end(placeholder, width);
+        let padding = " ".repeat(width - display_width(shown));
+        return format!("[\x1b[2;4m{shown}{padding}\x1b[0m]");
+    }
+    // leave a column for the cursor
+    let shown = fit_end(text, width - usize::from(editing));
+    let cursor = if editing { "\x1b[7m \x1b[0;4m" } else { "" };
+    let padding = " ".repeat(width - usize::from(editing) - display_width(shown));
+    format!("[\x1b[4m{shown}{cursor}{padding}\x1b[0m]")
+}
+
+/// pure fn to draw a button
+fn button(label: &str) -> String {
+    format!("\x1b[30;47m {} \x1b[0m", label.trim())
+}
+
+fn display_input(properties: &BTreeMap<RStr, RStr>, forms: &mut FormState) -> Vec<TerminalLine> {
+    let value = attribute(properties, "value");
+    let kind = match attribute(properties, "type").to_lowercase().as_str() {
+        "password" => ControlKind::Password,
+        "hidden" => ControlKind::Hidden,
+        "checkbox" => ControlKind::Checkbox,
+        "radio" => ControlKind::Radio,
+        "submit" | "image" => ControlKind::Submit,
+        "reset" => ControlKind::Reset,
+        "button" => ControlKind::Button,
+        _ => ControlKind::Text,
+    };
+    let default = match kind {
+        ControlKind::Text | ControlKind::Password => ControlValue::Text(value.to_string()),
+        ControlKind::Checkbox | ControlKind::Radio => {
+            ControlValue::Checked(properties.contains_key("checked"))
+        }
+        _ => ControlValue::Fixed,
+    };
+    let (id, current) = forms.add_control(
+        properties,
+        kind.clone(),
+        attribute(properties, "name"),
+        value.clone(),
+        default,
+    );
+    let editing = forms.editing() == Some(id);
+    let placeholder = attribute(properties, "placeholder");
+    let width = numeric_attribute(properties, "size", 20);
+    let text = match (kind, current) {
+        (ControlKind::Hidden, _) => return Vec::new(),
+        (ControlKind::Text, ControlValue::Text(text)) => {
+            text_field(&text, &placeholder, width, editing)
+        }
+        (ControlKind::Password, ControlValue::Text(text)) => text_field(
+            &"*".repeat(text.chars().count()),
+            &placeholder,
+            width,
+            editing,
+        ),
+        (ControlKind::Checkbox, ControlValue::Checked(checked)) => {
+            String::from(if checked { "[x]" } else { "[ ]" })
+        }
+        (ControlKind::Radio, ControlValue::Checked(checked)) => {
+            String::from(if checked { "(*)" } else { "( )" })
+        }
+        (ControlKind::Submit, _) if value.is_empty() => button("Submit"),
+        (ControlKind::Reset, _) if value.is_empty() => button("Reset"),
+        _ => button(&value),
+    };
+    vec![TerminalLine::from(text).with_interaction(InteractionType::FormControl(id))]
+}
+
+fn display_textarea(
+    properties: &BTreeMap<RStr, RStr>,
+    content: &str,
+    forms: &mut FormState,
+) -> Vec<TerminalLine> {
+    let (id, current) = forms.add_control(
+        properties,
+        ControlKind::TextArea,
+        attribute(properties, "name"),
+        "".into(),
+        ControlValue::Text(String::from(content.trim())),
+    );
+    let editing = forms.editing() == Some(id);
+    let width = numeric_attribute(properties, "cols", 40);
+    let rows = numeric_attribute(properties, "rows", 2);
+    let text = match current {
+        ControlValue::Text(text) => text,
+        _ => String::new(),
+    };
+    let mut lines: Vec<&str> = text.split('\n').collect();
+    let last = lines.len() - 1;
+    lines.resize(rows.max(lines.len()), "");
+    lines
+        .into_iter()
+        .enumerate()
+        .map(|(i, line)| {
+            TerminalLine::from(text_field(line, "", width, editing && i == last))
+                .with_interaction(InteractionType::FormControl(id))
         })
-    })
-    .unwrap_or_else(|| {
-        let alt = properties
-            .get("alt")
-            .map_or_else(|| src.map_or("", |src| src), |alt| alt);
-        if alt.is_empty() {
-            vec![RStr::from("[image]").into()]
-        } else {
-            vec![RStr::from(format!("[image: {alt}]")).into()]
+        .collect()
+}
+
+fn display_select(
+    properties: &BTreeMap<RStr, RStr>,
+    children: &[DocElement],
+    forms: &mut FormState,
+) -> Vec<TerminalLine> {
+    let mut options = Vec::new();
+    let mut selected = 0;
+    let mut children_buf: Vec<&DocElement> = children.iter().rev().collect();
+    while let Some(child) = children_buf.pop() {
+        if let DocElement::HtmlElement {
+            name,
+            children,
+            properties,
+        } = child
+        {
+            if &**name == "option" {
+                let label: RStr = child.text_content().trim().into();
+                if properties.contains_key("selected") {
+                    selected = options.len();
+                }
+                options.push((properties.get("value").unwrap_or(&label).clone(), label));
+            } else {
+                // options can be inside an `<optgroup>`
+                children_buf.extend(children.iter().rev());
+            }
         }
-    })
+    }
+    let (id, current) = forms.add_control(
+        properties,
+        ControlKind::Select(options.clone()),
+        attribute(properties, "name"),
+        "".into(),
+        ControlValue::Selected(selected),
+    );
+    let label = match current {
+        ControlValue::Selected(selected) => options.get(selected).map(|(_, label)| label.clone()),
+        _ => None,
+    };
+    vec![
+        TerminalLine::from(format!("[\x1b[4m{}\x1b[0m ▾]", label.unwrap_or_default()))
+            .with_interaction(InteractionType::FormControl(id)),
+    ]
+}
+
+fn display_button(
+    properties: &BTreeMap<RStr, RStr>,
+    label: &str,
+    forms: &mut FormState,
+) -> Vec<TerminalLine> {
+    let kind = match attribute(properties, "type").to_lowercase().as_str() {
+        "reset" => ControlKind::Reset,
+        "button" => ControlKind::Button,
+        _ => ControlKind::Submit,
+    };
+    let (id, _) = forms.add_control(
+        properties,
+        kind,
+        attribute(properties, "name"),
+        attribute(properties, "value"),
+        ControlValue::Fixed,
+    );
+    vec![TerminalLine::from(button(label)).with_interaction(InteractionType::FormControl(id))]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        sync::{Arc, Mutex},
+    };
+
+    use crossterm::terminal::SetTitle;
+
+    use super::{layout_block, DocElement, Flow, TextDirection};
+    use crate::{
+        cacher::ByteCacher,
+        config::Config,
+        types::{FormState, RStr},
+    };
+
+    fn element(name: &str, dir: Option<&str>, children: Vec<DocElement>) -> DocElement {
+        DocElement::HtmlElement {
+            name: name.into(),
+            children,
+            properties: dir
+                .map(|dir| BTreeMap::from([("dir".into(), dir.into())]))
+                .unwrap_or_default(),
+        }
+    }
+
+    fn text(txt: &str) -> DocElement {
+        DocElement::Text(txt.into())
+    }
+
+    #[test]
+    fn bidi_blocks() {
+        let config = Config {
+            disk_cache: false,
+            ..Config::default()
+        };
+        let cacher = Arc::new(Mutex::new(ByteCacher::open(&config, None).unwrap()));
+        let layout = |children: &[DocElement]| {
+            let flow = Flow {
+                dir: TextDirection::Auto,
+                width: 20,
+                paragraph: None,
+            };
+            layout_block(
+                children,
+                &mut SetTitle(RStr::from("")),
+                &cacher,
+                "https://example.com/",
+                flow,
+                &mut FormState::default(),
+                false,
+            )
+            .iter()
+            .map(|tl| tl.display(false))
+            .collect::<Vec<_>>()
+        };
+        // the link is part of a paragraph that starts right-to-left, so it lines up on the right with the rest
+        let lines = layout(&[element(
+            "p",
+            None,
+            vec![text("שלום"), element("b", None, vec![text("hello world")])],
+        )]);
+        assert_eq!(
+            lines,
+            [
+                format!(" {}םולש", " ".repeat(16)),
+                format!(" {}\x1b[1mhello world\x1b[0m", " ".repeat(9)),
+            ]
+        );
+        // a heading's box fits in the width it's given, however its text is aligned
+        let lines = layout(&[element("h2", Some("rtl"), vec![text("כותרת")])]);
+        assert!(lines.iter().all(|line| line.chars().count() <= 21));
+        assert_eq!(lines[1], format!(" ║{}תרתוכ║", " ".repeat(13)));
+        // a left-to-right paragraph inside a right-to-left block keeps to the left
+        let lines = layout(&[element(
+            "div",
+            Some("rtl"),
+            vec![element("p", Some("ltr"), vec![text("hello")])],
+        )]);
+        assert_eq!(lines, [" hello"]);
+    }
 }
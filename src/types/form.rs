@@ -0,0 +1,628 @@
+use std::{
+    collections::BTreeMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use url::{form_urlencoded, Url};
+
+use super::{DocElement, RStr};
+
+/// what kind of widget a form control is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlKind {
+    Text,
+    Password,
+    Hidden,
+    Checkbox,
+    Radio,
+    Submit,
+    Reset,
+    /// a button that doesn't do anything without javascript
+    Button,
+    TextArea,
+    /// a dropdown, with the `(value, label)` of each option
+    Select(Vec<(RStr, RStr)>),
+}
+
+/// a single control in a form, as laid out on the page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormControl {
+    /// index of the form this control is inside
+    pub form: Option<usize>,
+    /// the `id` of the form its `form` attribute says it belongs to instead, wherever that is on the page
+    pub form_id: Option<RStr>,
+    /// whether it or a `<fieldset>` it's in is disabled, so it can't be changed and isn't sent
+    pub disabled: bool,
+    pub kind: ControlKind,
+    pub name: RStr,
+    /// the `value` attribute; what a checked checkbox or clicked button sends
+    pub value: RStr,
+}
+
+/// the current value of a control
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlValue {
+    Text(String),
+    Checked(bool),
+    Selected(usize),
+    /// buttons and hidden inputs can't be changed
+    Fixed,
+}
+
+/// how a form is sent, from its `method` attribute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormMethod {
+    Get,
+    Post,
+}
+
+/// how a form's fields are encoded, from its `enctype` attribute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormEncoding {
+    UrlEncoded,
+    Multipart,
+    TextPlain,
+}
+
+/// a form that's ready to send, with the name and value of each successful control
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormSubmission {
+    pub method: FormMethod,
+    /// where to send the form, already resolved against the page
+    pub action: Url,
+    pub encoding: FormEncoding,
+    pub fields: Vec<(RStr, String)>,
+}
+
+impl FormSubmission {
+    /// pure fn to get the url a GET submission loads, with the fields as its query
+    pub fn url(&self) -> String {
+        let mut url = self.action.clone();
+        url.set_query(None);
+        url.set_fragment(None);
+        if !self.fields.is_empty() {
+            url.query_pairs_mut()
+                .extend_pairs(self.fields.iter().map(|(name, value)| (&**name, value)));
+        }
+        url.into()
+    }
+
+    /// get the content type and body of a POST submission
+    pub fn body(&self) -> (String, Vec<u8>) {
+        match self.encoding {
+            FormEncoding::UrlEncoded => (
+                String::from("application/x-www-form-urlencoded"),
+                form_urlencoded::Serializer::new(String::new())
+                    .extend_pairs(self.fields.iter().map(|(name, value)| (&**name, value)))
+                    .finish()
+                    .into_bytes(),
+            ),
+            FormEncoding::Multipart => {
+                let boundary = self.boundary(
+                    SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |time| time.as_nanos()),
+                );
+                let body = self
+                    .fields
+                    .iter()
+                    .map(|(name, value)| {
+                        format!(
+                            "--{boundary}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{value}\r\n",
+                            name.replace('"', "%22")
+                                .replace('\r', "%0D")
+                                .replace('\n', "%0A")
+                        )
+                    })
+                    .chain([format!("--{boundary}--\r\n")])
+                    .collect::<Vec<_>>()
+                    .concat();
+                (
+                    format!("multipart/form-data; boundary={boundary}"),
+                    body.into_bytes(),
+                )
+            }
+            FormEncoding::TextPlain => (
+                String::from("text/plain"),
+                self.fields
+                    .iter()
+                    .map(|(name, value)| format!("{name}={value}\r\n"))
+                    .collect::<Vec<_>>()
+                    .concat()
+                    .into_bytes(),
+            ),
+        }
+    }
+
+    /// pure fn to pick a multipart boundary, counting up from `seed` until it's one no field has in it
+    fn boundary(&self, seed: u128) -> String {
+        (seed..=u128::MAX)
+            .map(|n| format!("----NasirFormBoundary{n:x}"))
+            .find(|boundary| {
+                self.fields.iter().all(|(name, value)| {
+                    !name.contains(boundary.as_str()) && !value.contains(boundary.as_str())
+                })
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// all the forms on a page, along with what the user has put into them.
+/// The layout is rebuilt each time the page is displayed, but edits are kept
+#[derive(Debug, Default)]
+pub struct FormState {
+    /// attributes of each `<form>` element, in page order
+    forms: Vec<BTreeMap<RStr, RStr>>,
+    /// each control on the page, in page order
+    controls: Vec<FormControl>,
+    /// the values each control had in the html
+    defaults: Vec<ControlValue>,
+    /// values the user has changed, by control index
+    values: BTreeMap<usize, ControlValue>,
+    /// the form whose children are currently being displayed
+    current_form: Option<usize>,
+    /// whether the children being displayed are in a disabled `<fieldset>`
+    in_disabled_fieldset: bool,
+    /// the control the user is typing into
+    editing: Option<usize>,
+    /// the elements the page was laid out from, so it can be laid out again when a control changes
+    document: Option<DocElement>,
+}
+
+impl FormState {
+    /// forget the page layout before displaying it again
+    pub fn clear_layout(&mut self) {
+        self.forms.clear();
+        self.controls.clear();
+        self.defaults.clear();
+        self.current_form = None;
+        self.in_disabled_fieldset = false;
+    }
+
+    /// keep the elements the page was laid out from
+    pub fn set_document(&mut self, document: DocElement) {
+        self.document = Some(document);
+    }
+
+    /// take the elements the page was laid out from, to lay it out again
+    pub const fn take_document(&mut self) -> Option<DocElement> {
+        self.document.take()
+    }
+
+    /// start a `<form>` element; returns the form that was open before it
+    pub fn open_form(&mut self, properties: &BTreeMap<RStr, RStr>) -> Option<usize> {
+        self.forms.push(properties.clone());
+        self.current_form.replace(self.forms.len() - 1)
+    }
+
+    /// end a `<form>` element, restoring whatever form was open before it
+    pub const fn close_form(&mut self, previous: Option<usize>) {
+        self.current_form = previous;
+    }
+
+    /// start a `<fieldset>` element; returns whether the one around it was disabled
+    pub fn open_fieldset(&mut self, properties: &BTreeMap<RStr, RStr>) -> bool {
+        let disabled = self.in_disabled_fieldset || properties.contains_key("disabled");
+        std::mem::replace(&mut self.in_disabled_fieldset, disabled)
+    }
+
+    /// end a `<fieldset>` element, going back to whether the one around it was disabled
+    pub const fn close_fieldset(&mut self, previous: bool) {
+        self.in_disabled_fieldset = previous;
+    }
+
+    /// add a control to the current form, or the one its `form` attribute names; returns its index and current
+    /// value
+    pub fn add_control(
+        &mut self,
+        properties: &BTreeMap<RStr, RStr>,
+        kind: ControlKind,
+        name: RStr,
+        value: RStr,
+        default: ControlValue,
+    ) -> (usize, ControlValue) {
+        let id = self.controls.len();
+        self.controls.push(FormControl {
+            form: self.current_form,
+            form_id: properties.get("form").cloned(),
+            disabled: self.in_disabled_fieldset || properties.contains_key("disabled"),
+            kind,
+            name,
+            value,
+        });
+        self.defaults.push(default);
+        (id, self.value(id))
+    }
+
+    /// pure fn to get a control by its index
+    pub fn control(&self, id: usize) -> Option<&FormControl> {
+        self.controls.get(id)
+    }
+
+    /// pure fn to get the form a control belongs to: the one its `form` attribute names if it has one, or
+    /// else the one it's inside
+    fn owner(&self, control: &FormControl) -> Option<usize> {
+        control.form_id.as_ref().map_or(control.form, |form_id| {
+            self.forms
+                .iter()
+                .position(|form| form.get("id") == Some(form_id))
+        })
+    }
+
+    /// pure fn to get the current value of a control
+    pub fn value(&self, id: usize) -> ControlValue {
+        self.values
+            .get(&id)
+            .or_else(|| self.defaults.get(id))
+            .cloned()
+            .unwrap_or(ControlValue::Fixed)
+    }
+
+    /// pure fn to get the control the user is typing into
+    pub const fn editing(&self) -> Option<usize> {
+        self.editing
+    }
+
+    /// pure fn to check if a control takes more than one line of text
+    pub fn is_multiline(&self, id: usize) -> bool {
+        self.control(id)
+            .is_some_and(|control| control.kind == ControlKind::TextArea)
+    }
+
+    /// respond to the user pressing enter on a control; returns whether it submits its form
+    pub fn activate(&mut self, id: usize) -> bool {
+        let Some(control) = self.controls.get(id).filter(|control| !control.disabled) else {
+            return false;
+        };
+        match (&control.kind, self.value(id)) {
+            (ControlKind::Text | ControlKind::Password | ControlKind::TextArea, _) => {
+                self.editing = Some(id);
+            }
+            (ControlKind::Checkbox, ControlValue::Checked(checked)) => {
+                self.values.insert(id, ControlValue::Checked(!checked));
+            }
+            (ControlKind::Radio, _) => {
+                // only one radio button with the same name in the same form can be checked
+                let (form, name) = (self.owner(control), control.name.clone());
+                for (other_id, other) in self.controls.iter().enumerate() {
+                    if other.kind == ControlKind::Radio
+                        && self.owner(other) == form
+                        && other.name == name
+                    {
+                        self.values
+                            .insert(other_id, ControlValue::Checked(other_id == id));
+                    }
+                }
+            }
+            (ControlKind::Select(options), ControlValue::Selected(selected)) => {
+                let next = (selected + 1) % options.len().max(1);
+                self.values.insert(id, ControlValue::Selected(next));
+            }
+            (ControlKind::Reset, _) => {
+                let form = self.owner(control);
+                let owners: Vec<Option<usize>> = self
+                    .controls
+                    .iter()
+                    .map(|other| self.owner(other))
+                    .collect();
+                self.values
+                    .retain(|other_id, _| owners.get(*other_id).is_none_or(|&owner| owner != form));
+            }
+            (ControlKind::Submit, _) => return true,
+            _ => {}
+        }
+        false
+    }
+
+    /// pure fn to gather up the form a control belongs to so it can be sent, if it's in one.
+    /// `submitter` is the button that was pressed, or the text box the user pressed enter in
+    pub fn submit(&self, submitter: usize, page_url: &str) -> Option<FormSubmission> {
+        let form = self
+            .control(submitter)
+            .filter(|control| !control.disabled)
+            .and_then(|control| self.owner(control))?;
+        let properties = self.forms.get(form);
+        let attribute = |key: &str| {
+            properties
+                .and_then(|properties| properties.get(key))
+                .map(|value| value.trim().to_lowercase())
+                .unwrap_or_default()
+        };
+        let page_url = Url::parse(page_url).ok()?;
+        let action = match properties.and_then(|properties| properties.get("action")) {
+            Some(action) if !action.trim().is_empty() => page_url.join(action.trim()).ok()?,
+            _ => page_url,
+        };
+        let method = match attribute("method").as_str() {
+            "post" => FormMethod::Post,
+            _ => FormMethod::Get,
+        };
+        let encoding = match attribute("enctype").as_str() {
+            "multipart/form-data" => FormEncoding::Multipart,
+            "text/plain" => FormEncoding::TextPlain,
+            _ => FormEncoding::UrlEncoded,
+        };
+        let fields = self
+            .controls
+            .iter()
+            .enumerate()
+            .filter(|(_, control)| {
+                self.owner(control) == Some(form) && !control.disabled && !control.name.is_empty()
+            })
+            .filter_map(|(id, control)| {
+                let value = match (&control.kind, self.value(id)) {
+                    (
+                        ControlKind::Text | ControlKind::Password | ControlKind::TextArea,
+                        ControlValue::Text(text),
+                    ) => text,
+                    (ControlKind::Hidden, _) => control.value.to_string(),
+                    (ControlKind::Checkbox | ControlKind::Radio, ControlValue::Checked(true)) => {
+                        if control.value.is_empty() {
+                            String::from("on")
+                        } else {
+                            control.value.to_string()
+                        }
+                    }
+                    (ControlKind::Select(options), ControlValue::Selected(selected)) => {
+                        options.get(selected)?.0.to_string()
+                    }
+                    // only the button that was pressed gets sent
+                    (ControlKind::Submit, _) if id == submitter => control.value.to_string(),
+                    _ => return None,
+                };
+                Some((control.name.clone(), value))
+            })
+            .collect();
+        Some(FormSubmission {
+            method,
+            action,
+            encoding,
+            fields,
+        })
+    }
+
+    /// stop typing into a control
+    pub const fn stop_editing(&mut self) {
+        self.editing = None;
+    }
+
+    /// add a character to the control being edited
+    pub fn type_char(&mut self, c: char) {
+        if let Some(id) = self.editing {
+            let mut text = match self.value(id) {
+                ControlValue::Text(text) => text,
+                _ => String::new(),
+            };
+            text.push(c);
+            self.values.insert(id, ControlValue::Text(text));
+        }
+    }
+
+    /// remove the last character from the control being edited
+    pub fn backspace(&mut self) {
+        if let Some(id) = self.editing {
+            if let ControlValue::Text(mut text) = self.value(id) {
+                text.pop();
+                self.values.insert(id, ControlValue::Text(text));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use url::Url;
+
+    use super::{ControlKind, ControlValue, FormEncoding, FormMethod, FormState, FormSubmission};
+
+    #[test]
+    fn radio_buttons() {
+        let mut forms = FormState::default();
+        forms.open_form(&BTreeMap::new());
+        for checked in [true, false] {
+            forms.add_control(
+                &BTreeMap::new(),
+                ControlKind::Radio,
+                "size".into(),
+                "".into(),
+                ControlValue::Checked(checked),
+            );
+        }
+        forms.activate(1);
+        assert_eq!(forms.value(0), ControlValue::Checked(false));
+        assert_eq!(forms.value(1), ControlValue::Checked(true));
+    }
+
+    #[test]
+    fn typing() {
+        let mut forms = FormState::default();
+        forms.add_control(
+            &BTreeMap::new(),
+            ControlKind::Text,
+            "q".into(),
+            "".into(),
+            ControlValue::Text(String::from("ab")),
+        );
+        forms.activate(0);
+        forms.type_char('c');
+        forms.backspace();
+        forms.type_char('d');
+        forms.stop_editing();
+        // edits survive the page being laid out again
+        forms.clear_layout();
+        let (_, value) = forms.add_control(
+            &BTreeMap::new(),
+            ControlKind::Text,
+            "q".into(),
+            "".into(),
+            ControlValue::Text(String::from("ab")),
+        );
+        assert_eq!(value, ControlValue::Text(String::from("abd")));
+    }
+
+    #[test]
+    fn submission() {
+        let mut forms = FormState::default();
+        let previous = forms.open_form(&BTreeMap::from([("action".into(), "/search".into())]));
+        forms.add_control(
+            &BTreeMap::new(),
+            ControlKind::Text,
+            "q".into(),
+            "".into(),
+            ControlValue::Text(String::from("rust lang")),
+        );
+        forms.add_control(
+            &BTreeMap::new(),
+            ControlKind::Checkbox,
+            "safe".into(),
+            "".into(),
+            ControlValue::Checked(false),
+        );
+        forms.add_control(
+            &BTreeMap::new(),
+            ControlKind::Submit,
+            "go".into(),
+            "Go".into(),
+            ControlValue::Fixed,
+        );
+        forms.close_form(previous);
+        // a control outside any form isn't sent with it, and has nothing to send itself
+        forms.add_control(
+            &BTreeMap::new(),
+            ControlKind::Text,
+            "stray".into(),
+            "".into(),
+            ControlValue::Text(String::from("secret")),
+        );
+        assert!(forms.activate(2));
+        let submission = forms.submit(2, "https://example.com/home?x=1").unwrap();
+        assert_eq!(submission.method, FormMethod::Get);
+        assert_eq!(
+            submission.url(),
+            "https://example.com/search?q=rust+lang&go=Go"
+        );
+        assert_eq!(submission.body().1, b"q=rust+lang&go=Go");
+        assert_eq!(forms.submit(3, "https://example.com/home"), None);
+    }
+
+    #[test]
+    fn multipart_boundary() {
+        let submission = FormSubmission {
+            method: FormMethod::Post,
+            action: Url::parse("https://example.com/upload").unwrap(),
+            encoding: FormEncoding::Multipart,
+            fields: vec![
+                (
+                    "a".into(),
+                    String::from("x\r\n------NasirFormBoundary10--\r\n"),
+                ),
+                ("------NasirFormBoundary11".into(), String::from("y")),
+            ],
+        };
+        // a field that has the boundary in it would end its part early
+        assert_eq!(submission.boundary(0x10), "----NasirFormBoundary12");
+        let (content_type, body) = submission.body();
+        let boundary = content_type.split_once("boundary=").unwrap().1;
+        let body = String::from_utf8(body).unwrap();
+        assert_eq!(body.matches(boundary).count(), 3);
+        assert!(body.ends_with(&format!("--{boundary}--\r\n")));
+    }
+
+    #[test]
+    fn disabled_controls() {
+        let mut forms = FormState::default();
+        forms.open_form(&BTreeMap::new());
+        let disabled = BTreeMap::from([("disabled".into(), "".into())]);
+        forms.add_control(
+            &disabled,
+            ControlKind::Checkbox,
+            "own".into(),
+            "".into(),
+            ControlValue::Checked(true),
+        );
+        let outer = forms.open_fieldset(&disabled);
+        forms.add_control(
+            &BTreeMap::new(),
+            ControlKind::Text,
+            "fieldset".into(),
+            "".into(),
+            ControlValue::Text(String::from("x")),
+        );
+        forms.close_fieldset(outer);
+        forms.add_control(
+            &BTreeMap::new(),
+            ControlKind::Text,
+            "q".into(),
+            "".into(),
+            ControlValue::Text(String::from("y")),
+        );
+        // they can't be changed or typed into
+        assert!(!forms.activate(0));
+        assert_eq!(forms.value(0), ControlValue::Checked(true));
+        forms.activate(1);
+        assert_eq!(forms.editing(), None);
+        // and aren't sent
+        let submission = forms.submit(2, "https://example.com/").unwrap();
+        assert_eq!(submission.body().1, b"q=y");
+        assert_eq!(forms.submit(1, "https://example.com/"), None);
+    }
+
+    #[test]
+    fn form_attribute() {
+        let mut forms = FormState::default();
+        let previous = forms.open_form(&BTreeMap::from([
+            ("id".into(), "a".into()),
+            ("action".into(), "/a".into()),
+        ]));
+        forms.add_control(
+            &BTreeMap::new(),
+            ControlKind::Submit,
+            "in_a".into(),
+            "1".into(),
+            ControlValue::Fixed,
+        );
+        // inside one form but belonging to the other
+        forms.add_control(
+            &BTreeMap::from([("form".into(), "b".into())]),
+            ControlKind::Text,
+            "for_b".into(),
+            "".into(),
+            ControlValue::Text(String::from("inside")),
+        );
+        forms.close_form(previous);
+        // outside any form, naming a form further down the page
+        forms.add_control(
+            &BTreeMap::from([("form".into(), "b".into())]),
+            ControlKind::Text,
+            "outside".into(),
+            "".into(),
+            ControlValue::Text(String::from("after")),
+        );
+        let previous = forms.open_form(&BTreeMap::from([
+            ("id".into(), "b".into()),
+            ("action".into(), "/b".into()),
+        ]));
+        forms.add_control(
+            &BTreeMap::new(),
+            ControlKind::Submit,
+            "in_b".into(),
+            "2".into(),
+            ControlValue::Fixed,
+        );
+        forms.close_form(previous);
+        let a = forms.submit(0, "https://example.com/").unwrap();
+        assert_eq!(a.url(), "https://example.com/a?in_a=1");
+        let b = forms.submit(3, "https://example.com/").unwrap();
+        assert_eq!(
+            b.url(),
+            "https://example.com/b?for_b=inside&outside=after&in_b=2"
+        );
+        // pressing enter in a text box sends the form it belongs to
+        assert_eq!(
+            forms.submit(2, "https://example.com/").unwrap().action,
+            b.action
+        );
+    }
+}
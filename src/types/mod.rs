@@ -1,4 +1,5 @@
 mod doc_element;
+mod form;
 mod terminal_line;
 
 pub use prelude::*;
@@ -6,13 +7,14 @@ pub use prelude::*;
 pub mod prelude {
     use std::rc::Rc;
 
-    pub use super::doc_element::DocElement;
+    pub use super::doc_element::{DocElement, TextDirection};
+    pub use super::form::{ControlKind, ControlValue, FormMethod, FormState, FormSubmission};
     pub use super::terminal_handler::TermHandler;
     pub use super::terminal_line::{InteractionType, TerminalLine};
 
     pub type RStr = Rc<str>;
 
-    pub const SELF_CLOSING_TAGS: &[&str] = &["meta", "link", "image", "input", "img", "br"];
+    pub const SELF_CLOSING_TAGS: &[&str] = &["meta", "link", "image", "input", "img", "br", "wbr"];
 }
 
 mod terminal_handler {
@@ -1,6 +1,7 @@
 use std::cmp::max;
 
 use super::RStr;
+use crate::utils::display_width;
 
 use lazy_regex::lazy_regex;
 
@@ -82,6 +83,16 @@ impl TerminalLine {
         }
     }
 
+    /// pure fn to make the line a link: blue and underlined normally, and `(text)[href]` when focused
+    pub fn into_link(self, href: RStr) -> Self {
+        self
+            // >(text)[link] when focused
+            .map_focused(|str| format!("({str})[\x1b[94m{href}\x1b[0m]").into())
+            // blue underlined link when unfocused
+            .map_unfocused(|str| format!("\x1b[4;94m{str}\x1b[0m").into())
+            .with_interaction(InteractionType::Link(href))
+    }
+
     /// pure fn to set the html id
     #[allow(clippy::missing_const_for_fn)]
     pub fn with_id(self, id: RStr) -> Self {
@@ -93,7 +104,7 @@ impl TerminalLine {
 
     /// pure fn to check if the id matches
     pub fn check_id(&self, id: &str) -> bool {
-        self.html_id.as_ref().map_or(false, |str| id == &**str)
+        self.html_id.as_ref().is_some_and(|str| id == &**str)
     }
 
     pub fn max_visible_length(&self) -> usize {
@@ -122,14 +133,32 @@ impl TerminalLine {
             ..self
         }
     }
+
+    /// add spaces to the start of a line until its apparent size to the user matches the given value, if it's narrower
+    pub fn visible_left_pad(self, amount: usize) -> Self {
+        Self {
+            focused_text: format!(
+                "{}{}",
+                " ".repeat(amount.saturating_sub(self.focused_vis_len)),
+                self.focused_text
+            )
+            .into(),
+            unfocused_text: format!(
+                "{}{}",
+                " ".repeat(amount.saturating_sub(self.unfocused_vis_len)),
+                self.unfocused_text
+            )
+            .into(),
+            focused_vis_len: amount.max(self.focused_vis_len),
+            unfocused_vis_len: amount.max(self.unfocused_vis_len),
+            ..self
+        }
+    }
 }
 
-/// get the length of a str, ignoring ansi escape codes
+/// get the number of columns a str takes up, ignoring ansi escape codes
 fn get_visible_length(txt: &str) -> usize {
-    lazy_regex!("\x1b\\[[\\d;]+m")
-        .replace_all(txt, "")
-        .to_string()
-        .len()
+    display_width(&lazy_regex!("\x1b\\[[\\d;]+m").replace_all(txt, ""))
 }
 
 impl From<RStr> for TerminalLine {
@@ -160,8 +189,8 @@ impl From<&str> for TerminalLine {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum InteractionType {
-    // Input(String),
-    // Toggle(bool),
+    /// index of a control in the page's `FormState`
+    FormControl(usize),
     Image(RStr),
     Link(RStr),
     None,
@@ -1,30 +1,101 @@
 //! Small pure functions used in Nasir
 
+use std::net::IpAddr;
+
 use crate::types::RStr;
 use lazy_regex::lazy_regex;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use unicode_bidi::{get_base_direction, Direction, ParagraphBidiInfo, LTR_LEVEL, RTL_LEVEL};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+use url::{form_urlencoded, Url};
+
+/// schemes whose urls don't start with `//`, so they can't be told apart from `host:port` by shape
+const OPAQUE_SCHEMES: [&str; 3] = ["about", "data", "view-source"];
 
+/// pure fn to resolve a link on a page to the url it points at
 pub fn get_link_destination(current: &str, link: &RStr) -> RStr {
-    if link.starts_with("//") {
-        format!("https:{link}").into()
-    } else if link.starts_with('#') {
-        format!("{current}{link}").into()
-    } else if let Some(link) = link.strip_prefix('/') {
-        format!(
-            "{}{link}",
-            &current
-                .split('/')
-                .take(3)
-                .map(|s| format!("{s}/"))
-                .collect::<String>()
-        )
-        .into()
-    } else if let Some(link) = link.strip_prefix("..") {
-        todo!("This is a backtracked relative link")
-    } else if !link.contains('.') {
-        todo!("This is a relative link")
-    } else {
-        link.clone()
+    // a page without a proper url can only have absolute links
+    Url::parse(current)
+        .and_then(|current| current.join(link))
+        .map_or_else(|_| link.clone(), |url| String::from(url).into())
+}
+
+/// pure fn to check if a page may send the browser to a url. Only local pages and Nasir's own `about:` pages get
+/// to point at local files, so a page from the network can't read them into view or into the cache
+pub fn may_follow(current: &str, destination: &str) -> bool {
+    let has_scheme = |url: &str, scheme: &str| {
+        let url = url.strip_prefix("view-source:").unwrap_or(url);
+        url.get(..scheme.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(scheme))
+    };
+    !has_scheme(destination, "file:")
+        || has_scheme(current, "file:")
+        || has_scheme(current, "about:")
+}
+
+/// pure fn to turn what the user typed into the url to load, deciding once whether it's a url,
+/// a host without a scheme or a search. `search_url` has `%s` where the search terms go
+pub fn normalize_input(input: &str, search_url: &str) -> String {
+    let input = input.trim();
+    let scheme = input.split_once(':').map_or("", |(scheme, _)| scheme);
+    if lazy_regex!("^[a-zA-Z][a-zA-Z0-9+.-]*://").is_match(input)
+        || OPAQUE_SCHEMES.contains(&scheme.to_lowercase().as_str())
+    {
+        // a url that doesn't parse is left alone, so the error says what was typed
+        return Url::parse(input).map_or_else(|_| String::from(input), String::from);
+    }
+    if let Some(host) = typed_host(input) {
+        // local servers rarely have certificates
+        let scheme = if host == "localhost" || host.parse::<IpAddr>().is_ok() {
+            "http"
+        } else {
+            "https"
+        };
+        if let Ok(url) = Url::parse(&format!("{scheme}://{input}")) {
+            return String::from(url);
+        }
     }
+    let query: String = form_urlencoded::byte_serialize(input.as_bytes()).collect();
+    search_url.replace("%s", &query)
+}
+
+/// pure fn to get the url that answers a Gemini prompt or a Gopher search, with the answer as its whole query
+pub fn with_query(url: &str, input: &str) -> String {
+    let url = url.split('#').next().unwrap_or_default();
+    let base = url.split_once('?').map_or(url, |(base, _)| base);
+    format!("{base}?{}", utf8_percent_encode(input, NON_ALPHANUMERIC))
+}
+
+/// pure fn to get the host of input that looks like a url without a scheme, like `example.com/page`
+fn typed_host(input: &str) -> Option<&str> {
+    if input.is_empty() || input.contains(char::is_whitespace) {
+        return None;
+    }
+    let authority = input.split(['/', '?', '#']).next()?;
+    if let Some(ipv6) = authority
+        .strip_prefix('[')
+        .and_then(|rest| rest.split_once(']'))
+    {
+        return Some(ipv6.0);
+    }
+    let (host, port) = authority
+        .rsplit_once(':')
+        .map_or((authority, None), |(host, port)| (host, Some(port)));
+    if port.is_some_and(|port| port.is_empty() || !port.bytes().all(|b| b.is_ascii_digit())) {
+        return None;
+    }
+    let labels: Vec<&str> = host.strip_suffix('.').unwrap_or(host).split('.').collect();
+    // a single word is a search, unless it's localhost or has a port
+    let is_host = (labels.len() > 1 || host == "localhost" || port.is_some())
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && label
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        });
+    is_host.then_some(host)
 }
 
 /// replace things like &#x24; and &lt;
@@ -118,32 +189,157 @@ pub const fn rgb_to_256((r, g, b): (usize, usize, usize)) -> u8 {
     ((r * 36) + (g * 6) + b) + 16
 }
 
+/// soft hyphen, a break opportunity that shows a `-` only when the line breaks there
+pub const SOFT_HYPHEN: char = '\u{ad}';
+/// zero-width space, used for `<wbr>` break opportunities
+pub const ZERO_WIDTH_SPACE: char = '\u{200b}';
+
+/// pure fn to get the number of terminal columns a grapheme cluster takes up
+fn grapheme_width(grapheme: &str) -> usize {
+    if grapheme.starts_with([SOFT_HYPHEN, ZERO_WIDTH_SPACE]) {
+        0
+    } else {
+        grapheme.width()
+    }
+}
+
+/// pure fn to get the number of terminal columns a string takes up
+pub fn display_width(txt: &str) -> usize {
+    txt.graphemes(true).map(grapheme_width).sum()
+}
+
+/// pure fn to get the longest end of a string that fits in `width` columns
+pub fn fit_end(txt: &str, width: usize) -> &str {
+    let mut used = 0;
+    let mut start = txt.len();
+    for (idx, grapheme) in txt.grapheme_indices(true).rev() {
+        used += grapheme_width(grapheme);
+        if used > width {
+            break;
+        }
+        start = idx;
+    }
+    &txt[start..]
+}
+
+/// pure fn to split a word at the places it's allowed to break: after soft hyphens and
+/// zero-width spaces, and on either side of wide (CJK) characters
+fn break_opportunities(word: &str) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    for (idx, grapheme) in word.grapheme_indices(true) {
+        if grapheme.width() > 1 {
+            if start < idx {
+                chunks.push(&word[start..idx]);
+            }
+            chunks.push(grapheme);
+            start = idx + grapheme.len();
+        } else if grapheme.starts_with([SOFT_HYPHEN, ZERO_WIDTH_SPACE]) {
+            chunks.push(&word[start..idx + grapheme.len()]);
+            start = idx + grapheme.len();
+        }
+    }
+    if start < word.len() {
+        chunks.push(&word[start..]);
+    }
+    chunks
+}
+
+/// pure fn to turn a line's break opportunities into what the user sees
+fn finish_line(mut line: String) -> String {
+    if line.ends_with(SOFT_HYPHEN) {
+        line.pop();
+        line.push('-');
+    }
+    line.replace([SOFT_HYPHEN, ZERO_WIDTH_SPACE], "")
+}
+
+/// split text into lines no more than `width` columns wide
 pub fn wrap(txt: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
     let mut lines_buf = Vec::new();
     let mut current_line_buf = String::new();
+    let mut current_width = 0;
     for w in txt.split_whitespace() {
-        if w.len() >= width {
-            if !current_line_buf.is_empty() {
-                lines_buf.push(core::mem::take(&mut current_line_buf));
+        for (i, chunk) in break_opportunities(w).into_iter().enumerate() {
+            let chunk_width = display_width(chunk);
+            // a soft hyphen is drawn as `-` if the line breaks after it, so leave room for that
+            let needed = chunk_width + usize::from(chunk.ends_with(SOFT_HYPHEN));
+            // only the first chunk of a word is separated by a space
+            let mut sep = usize::from(i == 0 && !current_line_buf.is_empty());
+            if current_width + sep + needed > width && !current_line_buf.is_empty() {
+                lines_buf.push(finish_line(core::mem::take(&mut current_line_buf)));
+                current_width = 0;
+                sep = 0;
+            }
+            if sep == 1 {
+                current_line_buf.push(' ');
+                current_width += 1;
+            }
+            if needed <= width {
+                current_line_buf.push_str(chunk);
+                current_width += chunk_width;
+                continue;
+            }
+            // this chunk can't fit on any line, so it has to be split between graphemes
+            for grapheme in chunk.graphemes(true) {
+                // the line is being split here anyway, so a soft hyphen with no room for its `-` is left out
+                if grapheme.starts_with(SOFT_HYPHEN) && current_width >= width {
+                    continue;
+                }
+                let grapheme_width = grapheme_width(grapheme);
+                if current_width + grapheme_width > width && !current_line_buf.is_empty() {
+                    lines_buf.push(finish_line(core::mem::take(&mut current_line_buf)));
+                    current_width = 0;
+                }
+                current_line_buf.push_str(grapheme);
+                current_width += grapheme_width;
             }
-            lines_buf.push(String::from(w));
-            continue;
-        }
-        if current_line_buf.len() + w.len() >= width {
-            lines_buf.push(core::mem::take(&mut current_line_buf));
         }
-        current_line_buf.push(' ');
-        current_line_buf.push_str(w);
     }
     if !current_line_buf.is_empty() {
-        lines_buf.push(current_line_buf);
+        lines_buf.push(finish_line(current_line_buf));
     }
     lines_buf
 }
 
+/// pure fn to tell if text with no explicit direction should be laid out right-to-left,
+/// going off its first strong character
+pub fn starts_rtl(txt: &str) -> bool {
+    get_base_direction(txt) == Direction::Rtl
+}
+
+/// pure fn to reorder a line of text from the order it's written in to the order it's displayed in
+pub fn reorder_bidi(line: &str, rtl: bool) -> String {
+    let level = if rtl { RTL_LEVEL } else { LTR_LEVEL };
+    ParagraphBidiInfo::new(line, Some(level))
+        .reorder_line(0..line.len())
+        .into_owned()
+}
+
+/// pure fn to show a number of bytes in the biggest unit that keeps it above 1
+#[allow(clippy::cast_precision_loss)]
+pub fn format_bytes(bytes: usize) -> String {
+    let mut size = bytes as f64;
+    for unit in ["B", "KB", "MB"] {
+        if size < 1024.0 {
+            return if unit == "B" {
+                format!("{bytes} B")
+            } else {
+                format!("{size:.1} {unit}")
+            };
+        }
+        size /= 1024.0;
+    }
+    format!("{size:.1} GB")
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{get_link_destination, rgb_to_256, transform_html_text};
+    use super::{
+        display_width, fit_end, format_bytes, get_link_destination, may_follow, normalize_input,
+        reorder_bidi, rgb_to_256, starts_rtl, transform_html_text, with_query, wrap,
+    };
 
     #[test]
     fn transform() {
@@ -158,6 +354,83 @@ mod tests {
             get_link_destination("https://docs.rs/releases/2", &"/releases/3".into()),
             "https://docs.rs/releases/3".into()
         );
+        assert_eq!(
+            get_link_destination(
+                "file:///target/doc/nasir/index.html",
+                &"../std/fs/fn.read.html#errors".into()
+            ),
+            "file:///target/doc/std/fs/fn.read.html#errors".into()
+        );
+        assert_eq!(
+            get_link_destination("https://a.com/page#old", &"#new".into()),
+            "https://a.com/page#new".into()
+        );
+        assert_eq!(
+            get_link_destination("https://a.com/", &"//b.com/x".into()),
+            "https://b.com/x".into()
+        );
+        // only local pages can link to local files
+        let passwd = get_link_destination("https://a.com/", &"file:///etc/passwd".into());
+        assert!(!may_follow("https://a.com/", &passwd));
+        assert!(!may_follow(
+            "gemini://a.com/",
+            "view-source:file:///etc/passwd"
+        ));
+        assert!(may_follow("file:///home/me/index.html", &passwd));
+        assert!(may_follow(
+            "view-source:file:///home/me/index.html",
+            &passwd
+        ));
+        assert!(may_follow("about:bookmarks", &passwd));
+        assert!(may_follow("https://a.com/", "https://b.com/"));
+    }
+
+    #[test]
+    fn wrapping() {
+        assert_eq!(
+            wrap("the quick brown fox", 10),
+            vec!["the quick", "brown fox"]
+        );
+        assert_eq!(wrap("café résumé", 6), vec!["café", "résumé"]);
+        assert_eq!(
+            wrap("日本語のテキスト", 6),
+            vec!["日本語", "のテキ", "スト"]
+        );
+        assert_eq!(wrap("extra\u{ad}ordinary", 8), vec!["extra-", "ordinary"]);
+        // the `-` a soft hyphen turns into takes up a column too
+        assert_eq!(
+            wrap("abc extra\u{ad}ordinary", 9),
+            vec!["abc", "extra-", "ordinary"]
+        );
+        assert_eq!(
+            wrap("extra\u{ad}ordinary", 5),
+            vec!["extra", "ordin", "ary"]
+        );
+        assert_eq!(wrap("long\u{200b}word", 6), vec!["long", "word"]);
+        assert_eq!(wrap("abcdefgh", 3), vec!["abc", "def", "gh"]);
+    }
+
+    #[test]
+    fn widths() {
+        assert_eq!(display_width("héllo"), 5);
+        assert_eq!(display_width("日本"), 4);
+        assert_eq!(display_width("e\u{301}"), 1);
+    }
+
+    #[test]
+    fn fitting() {
+        assert_eq!(fit_end("hello world", 5), "world");
+        assert_eq!(fit_end("日本語", 5), "本語");
+        assert_eq!(fit_end("hi", 5), "hi");
+    }
+
+    #[test]
+    fn bidi() {
+        assert!(starts_rtl("שלום world"));
+        assert!(!starts_rtl("hello עולם"));
+        assert_eq!(reorder_bidi("שלום", true), "םולש");
+        assert_eq!(reorder_bidi("hello עולם", false), "hello םלוע");
+        assert_eq!(reorder_bidi("שלום world", true), "world םולש");
     }
 
     #[test]
@@ -165,6 +438,43 @@ mod tests {
         assert_eq!(rgb_to_256((0, 0, 0)), 16);
     }
 
+    #[test]
+    fn omnibox() {
+        let normalize = |input| normalize_input(input, "https://search.example/?q=%s");
+        assert_eq!(normalize("example.com"), "https://example.com/");
+        assert_eq!(normalize("  HTTP://Example.com  "), "http://example.com/");
+        assert_eq!(
+            normalize("docs.rs/url?search=parse#top"),
+            "https://docs.rs/url?search=parse#top"
+        );
+        assert_eq!(normalize("bücher.de"), "https://xn--bcher-kva.de/");
+        assert_eq!(normalize("localhost:8080/api"), "http://localhost:8080/api");
+        assert_eq!(normalize("192.168.1.1"), "http://192.168.1.1/");
+        assert_eq!(normalize("[::1]:3000"), "http://[::1]:3000/");
+        assert_eq!(normalize("about:blank"), "about:blank");
+        assert_eq!(normalize("https://bad host/"), "https://bad host/");
+        assert_eq!(normalize("rust"), "https://search.example/?q=rust");
+        assert_eq!(
+            normalize("what is rust?"),
+            "https://search.example/?q=what+is+rust%3F"
+        );
+        assert_eq!(normalize("c++"), "https://search.example/?q=c%2B%2B");
+        assert_eq!(normalize("foo:bar"), "https://search.example/?q=foo%3Abar");
+        assert_eq!(
+            with_query("gemini://a/search?old#top", "cats & dogs"),
+            "gemini://a/search?cats%20%26%20dogs"
+        );
+    }
+
+    #[test]
+    fn byte_sizes() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_bytes(5 * 1024 * 1024), "5.0 MB");
+        assert_eq!(format_bytes(3 << 30), "3.0 GB");
+    }
+
     // #[test]
     // fn visible_length() {
     //     assert_eq!(get_visible_length("\x1b[31;5;1;1;1mHeyy\x1b[0m"), 4);